@@ -0,0 +1,199 @@
+//! Async wrapper around the blocking [`crate::Device`].
+//!
+//! hidapi has no async reads, so the blocking device lives on a dedicated worker
+//! thread. Queries are shipped to it as jobs and answered through oneshot channels,
+//! while every report it reads in between is broadcast as a [`DeviceEvent`].
+
+use std::sync::mpsc::{self, TryRecvError};
+use std::time::Duration;
+
+use tokio::sync::{broadcast, oneshot};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{Capabilities, DeviceError, DeviceEvent, DeviceInfo};
+
+// How long the worker blocks on a read before checking for queued jobs
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Events buffered per subscriber before the slowest one starts missing some
+const EVENT_CAPACITY: usize = 64;
+
+type Job = Box<dyn FnOnce(&mut crate::Device) + Send>;
+
+pub struct Device {
+    jobs: mpsc::Sender<Job>,
+    events: broadcast::Receiver<DeviceEvent>,
+}
+
+impl Device {
+    /// Opens the headset like [`crate::Device::new`] without blocking the runtime.
+    pub async fn new() -> Result<Self, DeviceError> {
+        let device = tokio::task::spawn_blocking(crate::Device::new)
+            .await
+            .map_err(|_| DeviceError::WorkerStopped())??;
+        Ok(Self::from_blocking(device))
+    }
+
+    /// Moves an already opened blocking device onto a worker thread.
+    pub fn from_blocking(mut device: crate::Device) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (event_sender, events) = broadcast::channel(EVENT_CAPACITY);
+
+        std::thread::spawn(move || loop {
+            match job_receiver.try_recv() {
+                Ok(job) => {
+                    job(&mut device);
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            match device.wait_for_updates(POLL_INTERVAL) {
+                Ok(event) => {
+                    let _ = event_sender.send(event);
+                }
+                Err(DeviceError::NoResponse())
+                | Err(DeviceError::UnknownResponse(_, _))
+                | Err(DeviceError::UnknownCommand(_)) => {}
+                // The dongle is gone: dropping the sender ends every event stream.
+                Err(_) => break,
+            }
+        });
+
+        Device { jobs, events }
+    }
+
+    /// Runs `f` on the worker thread with exclusive access to the blocking device.
+    pub async fn with_device<T, F>(&self, f: F) -> Result<T, DeviceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut crate::Device) -> Result<T, DeviceError> + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |device| {
+                let _ = reply.send(f(device));
+            }))
+            .map_err(|_| DeviceError::WorkerStopped())?;
+        response.await.map_err(|_| DeviceError::WorkerStopped())?
+    }
+
+    /// Updates read by the worker, such as `ChargeLevel`, `MicMuted` or `HeadsetConnected`.
+    ///
+    /// Each call returns an independent stream. It ends when the device is lost, and
+    /// silently skips events if the consumer falls more than a few dozen behind.
+    pub fn events(&self) -> impl Stream<Item = DeviceEvent> {
+        BroadcastStream::new(self.events.resubscribe()).filter_map(Result::ok)
+    }
+
+    pub async fn capabilities(&self) -> Result<Capabilities, DeviceError> {
+        self.with_device(|device| Ok(device.capabilities())).await
+    }
+
+    pub async fn query_headset_connected(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_headset_connected())
+            .await
+    }
+
+    pub async fn query_battery_level(&self) -> Result<u8, DeviceError> {
+        self.with_device(|device| device.query_battery_level())
+            .await
+    }
+
+    pub async fn query_charging(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_charging()).await
+    }
+
+    pub async fn query_mic_connected(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_mic_connected())
+            .await
+    }
+
+    pub async fn query_mic_muted(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_mic_muted()).await
+    }
+
+    pub async fn query_playback_muted(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_playback_muted())
+            .await
+    }
+
+    pub async fn query_mic_monitored(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_mic_monitored())
+            .await
+    }
+
+    pub async fn query_timeout(&self) -> Result<u8, DeviceError> {
+        self.with_device(|device| device.query_timeout()).await
+    }
+
+    pub async fn query_monitor_volume(&self) -> Result<u8, DeviceError> {
+        self.with_device(|device| device.query_monitor_volume())
+            .await
+    }
+
+    pub async fn query_noise_gate(&self) -> Result<bool, DeviceError> {
+        self.with_device(|device| device.query_noise_gate()).await
+    }
+
+    pub async fn query_device_info(&self) -> Result<DeviceInfo, DeviceError> {
+        self.with_device(|device| device.query_device_info()).await
+    }
+
+    pub async fn refresh_state(&self) -> Result<(), DeviceError> {
+        self.with_device(|device| device.refresh_state()).await
+    }
+
+    pub async fn mute_mic(&self, mute: bool) -> Result<usize, DeviceError> {
+        self.with_device(move |device| device.mute_mic(mute))
+            .await
+    }
+
+    pub async fn mute_playback(&self, mute: bool) -> Result<usize, DeviceError> {
+        self.with_device(move |device| device.mute_playback(mute))
+            .await
+    }
+
+    pub async fn monitor_mic(&self, monitor: bool) -> Result<usize, DeviceError> {
+        self.with_device(move |device| device.monitor_mic(monitor))
+            .await
+    }
+
+    pub async fn set_timeout(&self, timeout: u8) -> Result<usize, DeviceError> {
+        self.with_device(move |device| device.set_timeout(timeout))
+            .await
+    }
+
+    pub async fn set_monitor_volume(&self, volume: i8) -> Result<usize, DeviceError> {
+        self.with_device(move |device| device.set_monitor_volume(volume))
+            .await
+    }
+
+    pub async fn set_noise_gate(&self, enabled: bool) -> Result<usize, DeviceError> {
+        self.with_device(move |device| device.set_noise_gate(enabled))
+            .await
+    }
+}
+
+#[tokio::test]
+async fn test_async_queries_and_events() {
+    use crate::emulator::{Emulator, HeadsetState, Script, ScriptEvent};
+
+    let script = Script::new().at(Duration::from_millis(200), ScriptEvent::MicMuted(true));
+    let emulator = Emulator::new(HeadsetState::default(), script);
+    let device = Device::from_blocking(crate::Device::with_transport(emulator));
+    let mut events = Box::pin(device.events());
+
+    assert!(matches!(device.query_battery_level().await, Ok(80)));
+    loop {
+        let event = tokio::time::timeout(Duration::from_secs(2), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+        if event == DeviceEvent::MicMuted(true) {
+            break;
+        }
+    }
+    assert!(matches!(device.query_mic_muted().await, Ok(true)));
+}
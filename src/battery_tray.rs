@@ -1,5 +1,16 @@
-use hyper_x_cloud_ii_wireless::Device;
-use ksni::{Tray, MenuItem, menu::{StandardItem}, ToolTip, TrayService, Handle};
+use std::sync::mpsc::Sender;
+
+use crate::history::{self, Estimate};
+use crate::tray_icon::{self, IconState};
+use crate::{Capabilities, Capability, DeviceState, Setting};
+use ksni::{Icon, Tray, MenuItem, menu::{CheckmarkItem, RadioGroup, RadioItem, StandardItem, SubMenu}, ToolTip, TrayService, Handle};
+
+// Monitor volume steps offered in the menu
+const MONITOR_VOLUMES: std::ops::RangeInclusive<i8> = -5..=5;
+// Pixmap sizes handed to the tray host, which picks the closest one
+const ICON_SIZES: [usize; 3] = [22, 32, 48];
+// Idle timeouts offered in the menu, in minutes. 0 disables it.
+const TIMEOUTS: [u8; 4] = [0, 10, 20, 30];
 
 pub struct TrayHandler {
     handle: Handle<BatteryTray>,
@@ -15,8 +26,8 @@ impl TrayHandler {
         }
     }
 
-    pub fn update(&self, device: &Device) {
-        self.handle.update(|tray: &mut BatteryTray| { tray.update(device); })
+    pub fn update(&self, state: &DeviceState, estimate: Option<Estimate>) {
+        self.handle.update(|tray: &mut BatteryTray| { tray.update(state, estimate); })
     }
 
     pub fn set_status(&mut self, message: &str) {
@@ -28,31 +39,50 @@ impl TrayHandler {
     }
 }
 
+/// Tray icon showing the headset state. Menu entries don't touch the headset,
+/// they send the chosen [`Setting`] to whoever owns it.
 #[derive(Debug)]
 pub struct BatteryTray {
     battery_level: u8,
     charging: Option<bool>,
     muted: Option<bool>,
     mic_connected: Option<bool>,
+    mic_monitored: Option<bool>,
+    monitor_volume: i8,
+    timeout: u8,
+    capabilities: Capabilities,
+    estimate: Option<Estimate>,
     status_message: Option<String>,
+    commands: Sender<Setting>,
 }
 
 impl BatteryTray {
-    pub fn new() -> Self {
+    pub fn new(commands: Sender<Setting>) -> Self {
         BatteryTray {
             battery_level: 0,
             charging: None,
             muted: None,
             mic_connected: None,
+            mic_monitored: None,
+            monitor_volume: 0,
+            timeout: 0,
+            capabilities: Capabilities::empty(),
+            estimate: None,
             status_message: Some("No device found".to_string()),
+            commands,
         }
     }
 
-    pub fn update(&mut self, device: &Device) {
-        self.battery_level = device.battery_level;
-        self.charging = device.charging;
-        self.muted = device.muted;
-        self.mic_connected = device.mic_connected;
+    pub fn update(&mut self, state: &DeviceState, estimate: Option<Estimate>) {
+        self.battery_level = state.battery_level;
+        self.charging = state.charging;
+        self.muted = state.muted;
+        self.mic_connected = state.mic_connected;
+        self.mic_monitored = state.mic_monitored;
+        self.monitor_volume = state.monitor_volume();
+        self.timeout = state.timeout;
+        self.capabilities = state.capabilities;
+        self.estimate = estimate;
     }
 
     pub fn set_status(&mut self, message: &str) {
@@ -62,14 +92,108 @@ impl BatteryTray {
     pub fn clear_status(&mut self) {
         self.status_message = None;
     }
+
+    fn send(&self, setting: Setting) {
+        let _ = self.commands.send(setting);
+    }
+
+    /// Whether a control can be used right now.
+    fn controllable(&self, capability: Capability) -> bool {
+        self.status_message.is_none() && self.capabilities.contains(capability)
+    }
+
+    fn settings_menu(&self) -> Vec<MenuItem<Self>> {
+        let muted = self.muted.unwrap_or(false);
+        let monitored = self.mic_monitored.unwrap_or(false);
+        let volume = self.monitor_volume;
+
+        vec![
+            CheckmarkItem {
+                label: "Mute microphone".into(),
+                checked: muted,
+                enabled: self.controllable(Capability::MicMute),
+                activate: Box::new(move |tray: &mut Self| tray.send(Setting::MicMuted(!muted))),
+                ..Default::default()
+            }
+            .into(),
+            CheckmarkItem {
+                label: "Monitor microphone".into(),
+                checked: monitored,
+                enabled: self.controllable(Capability::Sidetone),
+                activate: Box::new(move |tray: &mut Self| tray.send(Setting::MicMonitored(!monitored))),
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Monitor volume".into(),
+                enabled: self.controllable(Capability::SidetoneVolume),
+                submenu: vec![RadioGroup {
+                    selected: MONITOR_VOLUMES.clone().position(|step| step == volume).unwrap_or(usize::MAX),
+                    select: Box::new(|tray: &mut Self, index| {
+                        if let Some(step) = MONITOR_VOLUMES.clone().nth(index) {
+                            tray.send(Setting::MonitorVolume(step));
+                        }
+                    }),
+                    options: MONITOR_VOLUMES
+                        .map(|step| RadioItem {
+                            label: step.to_string(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Idle timeout".into(),
+                enabled: self.controllable(Capability::AutoOff),
+                submenu: vec![RadioGroup {
+                    selected: TIMEOUTS.iter().position(|&minutes| minutes == self.timeout).unwrap_or(usize::MAX),
+                    select: Box::new(|tray: &mut Self, index| tray.send(Setting::Timeout(TIMEOUTS[index]))),
+                    options: TIMEOUTS
+                        .iter()
+                        .map(|&minutes| RadioItem {
+                            label: match minutes {
+                                0 => "Never".to_string(),
+                                minutes => format!("{minutes} minutes"),
+                            },
+                            ..Default::default()
+                        })
+                        .collect(),
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+        ]
+    }
 }
 
 impl Tray for BatteryTray {
-    fn icon_name(&self) -> String {
-        "headset".into()
+    fn icon_pixmap(&self) -> Vec<Icon> {
+        let state = IconState {
+            connected: self.status_message.is_none(),
+            battery_level: self.battery_level,
+            charging: self.charging.unwrap_or(false),
+            muted: self.muted.unwrap_or(false),
+        };
+        ICON_SIZES
+            .iter()
+            .map(|&size| {
+                let pixmap = tray_icon::render(&state, size);
+                Icon {
+                    width: size as i32,
+                    height: size as i32,
+                    data: pixmap.data,
+                }
+            })
+            .collect()
     }
     fn menu(&self) -> Vec<MenuItem<Self>> {
-        vec![
+        let mut menu = self.settings_menu();
+        menu.push(MenuItem::Separator);
+        menu.push(
             StandardItem {
                 label: "Exit".into(),
                 icon_name: "application-exit".into(),
@@ -77,7 +201,8 @@ impl Tray for BatteryTray {
                 ..Default::default()
             }
             .into(),
-        ]
+        );
+        menu
     }
     fn tool_tip(&self) -> ToolTip {
         let description = match &self.status_message {
@@ -91,6 +216,12 @@ impl Tray for BatteryTray {
                         description += "\nNot charging";
                     }
                 }
+                if let Some(remaining) = self.estimate.and_then(|estimate| estimate.time_remaining) {
+                    description += &format!("\n{} remaining", history::format_duration(remaining));
+                }
+                if let Some(to_full) = self.estimate.and_then(|estimate| estimate.time_to_full) {
+                    description += &format!("\n{} to full", history::format_duration(to_full));
+                }
                 if let Some(muted) = self.muted {
                     if muted {
                         description += "\nMuted";
@@ -110,9 +241,9 @@ impl Tray for BatteryTray {
         };
         ToolTip {
             title: "HyperX Cloud II".to_string(),
-            description: description,
+            description,
             icon_name: "".into(),
             icon_pixmap: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,24 @@
-use clap::{Parser, Subcommand};
-use hyper_x_cloud_ii_core_wireless::{Device};
-use std::time::Duration;
+use clap::{Parser, Subcommand, ValueEnum};
+use hyper_x_cloud_ii_core_wireless::config::{Config, Profile};
+use hyper_x_cloud_ii_core_wireless::history;
+use hyper_x_cloud_ii_core_wireless::headset::Headset;
+use hyper_x_cloud_ii_core_wireless::report::Report;
+use hyper_x_cloud_ii_core_wireless::template::Template;
+use hyper_x_cloud_ii_core_wireless::{Capability, Device, DeviceError, DeviceSelector, Setting};
 #[derive(Parser, Debug)]
 #[clap(
     about = " A CLI tool to control HyperX Cloud II Core Wireless device."
 )]
 struct Args {
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "DEVICE",
+        help = "Headset to use: an index from `list`, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+
     #[command(
         subcommand,
     )]
@@ -26,6 +39,13 @@ enum Operation {
         )]
         mute_mic: Option<bool>,
 
+        #[arg(
+            short = 'p',
+            long,
+            help="Mute the headset's playback. True to mute, false to unmute."
+        )]
+        mute_playback: Option<bool>,
+
         #[arg(
             short = 's',
             long,
@@ -46,6 +66,13 @@ enum Operation {
             help="Monitor volume, -5 to 5."
         )]
         monitor_volume: Option<i8>,
+
+        #[arg(
+            short = 'n',
+            long,
+            help="Noise gate. True to enable, false to disable."
+        )]
+        noise_gate: Option<bool>,
     },
     #[command(
         arg_required_else_help = true,
@@ -63,7 +90,7 @@ enum Operation {
             short,
             long,
             value_name="PATTERN",
-            help="Generate a report based on the pattern. Possible values \"mstvCcbM\"
+            help="Generate a report based on the pattern. Possible values \"mstvCcbMnP\"
     m for mute
     s for monitor
     t for timeout
@@ -71,15 +98,88 @@ enum Operation {
     C for headset connected
     c for charging
     b for battery level
-    M for mic connected"
+    M for mic connected
+    n for noise gate
+    P for playback muted"
         )]
         generate_report: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            value_name = "FORMAT",
+            help = "Print the whole state in a versioned schema, unknown values as null."
+        )]
+        format: Option<Format>,
+
+        #[arg(
+            long,
+            value_name = "TEMPLATE",
+            help = "Print a line from a template, e.g. \"{battery}% {charging?charging:{muted?muted}}\". Fields: battery, charging, connected, mic_connected, muted, monitoring, monitor_volume, timeout, noise_gate, playback_muted, model, firmware."
+        )]
+        template: Option<Template>,
     },
+    #[command(about = "Print the model, firmware, serial number and supported features.")]
+    Info,
+    #[command(about = "List attached headsets.")]
+    List,
+    #[command(about = "Save and apply named sets of settings.")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Json,
+    Yaml,
+    /// HYPERX_<FIELD>=value lines
+    Env,
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileAction {
+    #[command(about = "Save the headset's current settings as NAME.")]
+    Save { name: String },
+    #[command(about = "Write every setting of NAME to the headset and check that it took.")]
+    Apply { name: String },
+    #[command(about = "List saved profiles.")]
+    List,
 }
 fn main() {
     let args: Args = Args::parse();
-    let mut device = match Device::new() {
-        Ok(device) => device,
+
+    if let Operation::List = args.op {
+        match Device::enumerate() {
+            Ok(descriptors) => {
+                for (index, descriptor) in descriptors.iter().enumerate() {
+                    println!("{index}: {descriptor}");
+                }
+            }
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Operation::Profile { action: ProfileAction::List } = args.op {
+        for name in Profile::list() {
+            println!("{name}");
+        }
+        return;
+    }
+
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    // Goes through hyperxd when it is running, so the two don't fight over the dongle.
+    let mut headset = match Headset::open(&selector) {
+        Ok(headset) => headset,
         Err(error) => {
             eprintln!("{error}");
             std::process::exit(1);
@@ -89,129 +189,238 @@ fn main() {
     match args.op {
         Operation::Set {
             mute_mic,
+            mute_playback,
             monitor_mic,
             timeout,
             monitor_volume,
+            noise_gate,
         } => {
-            match timeout {
-                Some(timeout) => match device.set_timeout(timeout) {
-                    Ok(_) => {}
-                    Err(_) => {}
-                },
-                None => {}
-            }
-
-            match mute_mic {
-                Some(mute) => match device.mute_mic(mute) {
-                    Ok(_) => {}
-                    Err(_) => {}
-                },
-                None => {}
-            }
-
-            match monitor_mic {
-                Some(monitor) => match device.monitor_mic(monitor) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        println!("{:?}", e)
-                    }
-                },
-                None => {}
+            if let Some(timeout) = timeout {
+                if let Err(error) = headset.apply(Setting::Timeout(timeout)) {
+                    eprintln!("{error}");
+                }
+            }
+
+            if let Some(mute) = mute_mic {
+                if let Err(error) = headset.apply(Setting::MicMuted(mute)) {
+                    eprintln!("{error}");
+                }
+            }
+
+            if let Some(mute) = mute_playback {
+                if let Err(error) = headset.apply(Setting::PlaybackMuted(mute)) {
+                    eprintln!("{error}");
+                }
+            }
+
+            if let Some(monitor) = monitor_mic {
+                if let Err(error) = headset.apply(Setting::MicMonitored(monitor)) {
+                    eprintln!("{error}");
+                }
             }
 
-            match monitor_volume {
-                Some(volume) => match device.set_monitor_volume(volume) {
-                    Ok(_) => {}
-                    Err(_) => {}
-                },
-                None => {}
+            if let Some(volume) = monitor_volume {
+                if let Err(error) = headset.apply(Setting::MonitorVolume(volume)) {
+                    eprintln!("{error}");
+                }
+            }
+
+            if let Some(enabled) = noise_gate {
+                if let Err(error) = headset.apply(Setting::NoiseGate(enabled)) {
+                    eprintln!("{error}");
+                }
             }
         }
         Operation::Get {
             print_device,
             generate_report,
+            format,
+            template,
         } => {
-            for _ in 0..8 {
-                match device.wait_for_updates(Duration::from_secs(1)) {
-                    Ok(_) => {}
-                    Err(error) => {
-                        println!("{:?}", error);
-                    }
-                }
+            if let Err(error) = headset.refresh_state() {
+                eprintln!("{error}");
             }
+            let state = headset.state();
+            let estimate = config
+                .history
+                .path()
+                .and_then(|path| history::load(&path).ok())
+                .and_then(|samples| history::estimate(&samples, &state, history::now()));
 
             if print_device {
-                println!("{}", device);
+                println!("{}", state);
+                if let Some(remaining) = estimate.and_then(|estimate| estimate.time_remaining) {
+                    println!("Time Remaining: {}", history::format_duration(remaining));
+                }
+                if let Some(to_full) = estimate.and_then(|estimate| estimate.time_to_full) {
+                    println!("Time To Full: {}", history::format_duration(to_full));
+                }
             }
 
-            match generate_report {
-                Some(pattern) => {
-                    for get in pattern.chars() {
-                        if get == 'm' {
-                            match device.muted {
-                                Some(status) => {
-                                    print!("{} ", status);
-                                }
+            if let Some(template) = template {
+                println!("{}", template.render(&state));
+            }
+
+            if let Some(format) = format {
+                let report = Report::from(&state).with_estimate(estimate.as_ref());
+                match format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+                    Format::Yaml => print!("{}", serde_yaml::to_string(&report).unwrap()),
+                    Format::Env => print!("{}", report.to_env()),
+                }
+            }
+
+            if let Some(pattern) = generate_report {
+                for get in pattern.chars() {
+                    if get == 'm' {
+                        match state.muted {
+                            Some(status) => {
+                                print!("{} ", status);
+                            }
+
+                            None => {
+                                print!("N/A ");
+                            }
+                        }
+                    } else if get == 's' {
+                        match state.mic_monitored {
+                            Some(status) => {
+                                print!("{} ", status);
+                            }
+
+                            None => {
+                                print!("N/A ");
+                            }
+                        }
+                    } else if get == 't' {
+                        if state.capabilities.contains(Capability::AutoOff) {
+                            print!("{} ", state.timeout);
+                        } else {
+                            print!("N/A ");
+                        }
+                    } else if get == 'v' {
+                        if state.capabilities.contains(Capability::SidetoneVolume) {
+                            print!("{} ", state.monitor_volume());
+                        } else {
+                            print!("N/A ");
+                        }
+
+                    } else if get == 'C' {
+                        match state.headset_connected {
+                            Some(status) => {
+                                print!("{} ", status);
+                            }
 
-                                None => {
-                                    print!("N/A");
-                                }
+                            None => {
+                                print!("N/A ");
+                            }
+                        }
+                    } else if get == 'c' {
+                        match state.charging {
+                            Some(status) => {
+                                print!("{} ", status);
                             }
-                        } else if get == 's' {
-                            match device.mic_monitored {
-                                Some(status) => {
-                                    print!("{} ", status);
-                                }
-
-                                None => {
-                                    print!("N/A");
-                                }
+
+                            None => {
+                                print!("N/A ");
                             }
-                        } else if get == 't' {
-                            print!("{} ", device.timeout);
+                        }
+                    } else if get == 'b' {
+                        if state.capabilities.contains(Capability::Battery) {
+                            print!("{} ", state.battery_level);
+                        } else {
+                            print!("N/A ");
+                        }
 
-                        } else if get == 'v' {
-                            print!("{} ", device.monitor_volume);
+                    } else if get == 'M' {
+                        match state.mic_connected {
+                            Some(status) => {
+                                print!("{} ", status);
+                            }
 
-                        } else if get == 'C' {
-                            match device.headset_connected {
-                                Some(status) => {
-                                    print!("{} ", status);
-                                }
+                            None => {
+                                print!("N/A ");
+                            }
+                        }
+                    } else if get == 'n' {
+                        match state.noise_gate {
+                            Some(status) => {
+                                print!("{} ", status);
+                            }
 
-                                None => {
-                                    print!("N/A");
-                                }
+                            None => {
+                                print!("N/A ");
                             }
-                        } else if get == 'c' {
-                            match device.charging {
-                                Some(status) => {
-                                    print!("{} ", status);
-                                }
-
-                                None => {
-                                    print!("N/A");
-                                }
+                        }
+                    } else if get == 'P' {
+                        match state.playback_muted {
+                            Some(status) => {
+                                print!("{} ", status);
                             }
-                        } else if get == 'b' {
-                            print!("{} ", device.battery_level);
-
-                        } else if get == 'M' {
-                            match device.mic_connected {
-                                Some(status) => {
-                                    print!("{} ", status);
-                                }
-
-                                None => {
-                                    print!("N/A");
-                                }
+
+                            None => {
+                                print!("N/A ");
                             }
                         }
                     }
                 }
-                None => {}
             }
         }
+        Operation::Info => match headset.device_info() {
+            Ok(info) => {
+                let state = headset.state();
+                println!("Model: {}", state.model);
+                println!("{}", info);
+                println!("Capabilities: {}", state.capabilities);
+            }
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        },
+        Operation::Profile { action: ProfileAction::Save { name } } => {
+            if let Err(error) = headset.refresh_state() {
+                eprintln!("{error}");
+            }
+            let state = headset.state();
+            if state.headset_connected != Some(true) {
+                eprintln!("{}", DeviceError::HeadsetDisconnected());
+                std::process::exit(1);
+            }
+            match Profile::from_state(&state).save(&name) {
+                Ok(path) => println!("Saved {name} to {}", path.display()),
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Operation::Profile { action: ProfileAction::Apply { name } } => {
+            let profile = match Profile::load(&name) {
+                Ok(profile) => profile,
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            };
+            for setting in profile.settings() {
+                if let Err(error) = headset.apply(setting) {
+                    eprintln!("{setting:?}: {error}");
+                }
+            }
+            // Read everything back rather than trusting the acknowledgements.
+            if let Err(error) = headset.refresh_state() {
+                eprintln!("{error}");
+            }
+            let failed = profile.mismatches(&headset.state());
+            for setting in &failed {
+                eprintln!("{setting:?} did not stick.");
+            }
+            if !failed.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Operation::List | Operation::Profile { action: ProfileAction::List } => unreachable!(),
     }
 }
 
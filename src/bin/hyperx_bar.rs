@@ -0,0 +1,145 @@
+use std::io::{self, BufRead, Write};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use clap::{Parser, ValueEnum};
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+use hyper_x_cloud_ii_core_wireless::reconnect::{HeadsetEvent, ReconnectingHeadset};
+use hyper_x_cloud_ii_core_wireless::status_bar::{self, ClickEvent, StatusBar};
+use hyper_x_cloud_ii_core_wireless::{DeviceError, DeviceSelector};
+
+#[derive(Parser, Debug)]
+#[clap(about = "Waybar, i3bar/swaybar and Polybar module for a HyperX Cloud II Core Wireless headset.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+
+    #[arg(short, long, value_enum, default_value = "waybar", help = "Bar to write for.")]
+    format: Format,
+
+    #[arg(
+        long,
+        value_name = "MODULE",
+        help = "Send the Polybar output to the custom/ipc module MODULE with polybar-msg instead of printing it."
+    )]
+    ipc: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// JSON lines for a custom module with `return-type: json`
+    Waybar,
+    /// The i3bar protocol, also spoken by swaybar; clicks run the `[bar.click]` actions
+    I3bar,
+    /// Lines with color tags for a custom/script module with `tail = true`
+    Polybar,
+}
+
+fn spawn_click_channel() -> Receiver<ClickEvent> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if let Some(event) = ClickEvent::parse(&line) {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+fn send_ipc(module: &str, text: &str) {
+    let result = Command::new("polybar-msg")
+        .args(["action", &format!("#{module}.send.{text}")])
+        .output();
+    match result {
+        Ok(output) if !output.status.success() => {
+            eprintln!("polybar-msg: {}", String::from_utf8_lossy(&output.stderr).trim())
+        }
+        Ok(_) => {}
+        Err(error) => eprintln!("polybar-msg: {error}"),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    let interval = |value| {
+        config::seconds(value).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        })
+    };
+    let retry = interval(config.reconnect_interval);
+    let poll_interval = interval(config.bar.poll_interval);
+    let bar = StatusBar::new(&config.bar).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    if args.ipc.is_some() && args.format != Format::Polybar {
+        eprintln!("--ipc only works with --format polybar.");
+        std::process::exit(1);
+    }
+    let mut device = ReconnectingHeadset::new(selector, retry);
+    let clicks = (args.format == Format::I3bar).then(spawn_click_channel);
+
+    if args.format == Format::I3bar {
+        println!("{}", status_bar::I3BAR_HEADER);
+    }
+    let mut last_line = None;
+
+    // Run loop
+    loop {
+        match device.wait_for_updates(poll_interval) {
+            Ok(HeadsetEvent::Disconnected) => eprintln!("Device disconnected."),
+            Ok(_) => {}
+            Err(DeviceError::NoResponse()) => (),
+            Err(DeviceError::UnknownResponse(_, _)) => (),
+            Err(DeviceError::UnknownCommand(_)) => (),
+            Err(error) => eprintln!("{error}"),
+        }
+        let mut state = device.state();
+
+        for click in clicks.iter().flat_map(Receiver::try_iter) {
+            if click.name.as_deref().is_some_and(|name| name != status_bar::BLOCK_NAME) {
+                continue;
+            }
+            if let Some(setting) = config.bar.click.action(click.button).setting(&state) {
+                // Later clicks of the batch build on this one, before the headset reports it.
+                if device.apply(setting).is_ok() {
+                    setting.apply_to(&mut state);
+                }
+            }
+        }
+
+        let status = bar.status(&state);
+        let line = match args.format {
+            Format::Waybar => status.to_waybar(),
+            Format::I3bar => status.to_i3bar(&config.bar.colors),
+            Format::Polybar => status.to_polybar(&config.bar.colors),
+        };
+        if last_line.as_ref() == Some(&line) {
+            continue;
+        }
+        match &args.ipc {
+            Some(module) => send_ipc(module, &line),
+            None => {
+                println!("{line}");
+                let _ = io::stdout().flush();
+            }
+        }
+        last_line = Some(line);
+    }
+}
@@ -0,0 +1,44 @@
+use clap::Parser;
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::dbus_service;
+use hyper_x_cloud_ii_core_wireless::DeviceSelector;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Publish a HyperX headset on the session bus as org.hyperx.CloudII.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use when hyperxd isn't running: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    let _ = Args::parse();
+    eprintln!("hyperx_dbus is only built for Linux.");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+
+    let retry = config::seconds(config.reconnect_interval).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    if let Err(error) = dbus_service::serve(&selector, retry) {
+        eprintln!("D-Bus error: {error}");
+        std::process::exit(1);
+    }
+}
@@ -1,125 +1,150 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
 use std::thread;
-use std::{io, sync::mpsc::TryRecvError, time::Duration};
-use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
-use hyper_x_cloud_ii_core_wireless::{Device, DeviceError};
+use clap::Parser;
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+use hyper_x_cloud_ii_core_wireless::headset::Headset;
+use hyper_x_cloud_ii_core_wireless::reconnect::{HeadsetEvent, ReconnectingHeadset};
+use hyper_x_cloud_ii_core_wireless::status_bar::{ClickActions, ClickEvent};
+use hyper_x_cloud_ii_core_wireless::template::{Template, Value};
+use hyper_x_cloud_ii_core_wireless::{DeviceError, DeviceSelector, DeviceState};
 
-fn pair_device() -> Device {
-    loop {
-        match Device::new() {
-            Ok(device) => break device,
-            Err(error) => {
-                eprintln!("{error}");
-            }
-        };
-        std::thread::sleep(std::time::Duration::from_secs(1));
-    }
+#[derive(Parser, Debug)]
+#[clap(about = "i3blocks block for a HyperX Cloud II Core Wireless headset.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+
+    #[arg(
+        long,
+        help = "Print the block once and exit, for i3blocks without interval=persist. Implied when BLOCK_BUTTON is set."
+    )]
+    once: bool,
 }
 
-fn handle_error(error: DeviceError, device: &mut Device) {
-    match error {
-        DeviceError::HidError(hidapi::HidError::HidApiError { message }) => {
-            if message == "No such device" {
-                eprintln!("No device found.");
-                println!("");
-                *device = pair_device();
-            } else if message.contains("device disconnected") {
-                eprintln!("Device disconnected.");
-                println!("");
-                *device = pair_device();
-            } else {
-                eprintln!("HID ERROR: {message}");
-                std::process::exit(1);
+// i3blocks writes the button number, or with `format=json` a click event, per line.
+fn spawn_click_channel() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if let Some(event) = ClickEvent::parse(&line) {
+                if tx.send(event.button).is_err() {
+                    break;
+                }
             }
         }
-        DeviceError::NoDeviceFound() => {
-            eprintln!("{}", DeviceError::NoDeviceFound());
-            device.clear_state();
-            *device = pair_device();
-        }
-        error => {
-            eprintln!("{error}");
-            device.clear_state();
-            *device = pair_device();
-        }
+    });
+    rx
+}
+
+// Shown alone while the headset or the dongle is away, and as the icon of an unplugged mic
+const OFF_ICON: &str = "";
+
+fn block_text(template: &Template, icon: &str, state: &DeviceState) -> String {
+    template.render_with(state, &[("icon", Value::from(icon))])
+}
+
+/// The block for `state`, `None` until the headset has told enough to draw it.
+fn block_line(template: &Template, state: &DeviceState) -> Option<String> {
+    if !state.headset_connected? {
+        return Some(OFF_ICON.to_string());
     }
+    let icon = match (state.mic_connected?, state.muted) {
+        (false, _) | (true, None) => OFF_ICON,
+        (true, Some(true)) => "",
+        (true, Some(false)) => "",
+    };
+    Some(block_text(template, icon, state))
 }
 
-fn spawn_stdin_channel() -> Receiver<String> {
-    let (tx, rx) = mpsc::channel::<String>();
-    thread::spawn(move || loop {
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-        tx.send(buffer).unwrap();
+/// Runs the action of the click i3blocks started the block for, then prints it.
+fn run_once(selector: &DeviceSelector, actions: &ClickActions, template: &Template, button: Option<u8>) {
+    let mut headset = Headset::open(selector).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
     });
-    rx
+    if let Err(error) = headset.refresh_state() {
+        eprintln!("{error}");
+    }
+    let setting = button.and_then(|button| actions.action(button).setting(&headset.state()));
+    if let Some(setting) = setting {
+        if let Err(error) = headset.apply(setting).and_then(|_| headset.refresh_state()) {
+            eprintln!("{error}");
+        }
+    }
+    if let Some(line) = block_line(template, &headset.state()) {
+        println!("{line}");
+    }
 }
 
 fn main() {
-    let mut device = pair_device();
-    let stdin_channel = spawn_stdin_channel();
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    let interval = |value| {
+        config::seconds(value).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        })
+    };
+    let retry = interval(config.reconnect_interval);
+    let poll_interval = interval(config.i3blocks.poll_interval);
+    let template = Template::parse_with(&config.i3blocks.template, &["icon"]).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    // i3blocks sets BLOCK_BUTTON when it runs a block that isn't persistent for a click.
+    let button = std::env::var("BLOCK_BUTTON")
+        .ok()
+        .and_then(|button| ClickEvent::parse(&button))
+        .map(|event| event.button);
+    if args.once || button.is_some() {
+        run_once(&selector, &config.i3blocks.click, &template, button);
+        return;
+    }
+    let mut device = ReconnectingHeadset::new(selector, retry);
+    let clicks = spawn_click_channel();
 
     // Run loop
     loop {
-        match device.wait_for_updates(Duration::from_secs(1)) {
+        match device.wait_for_updates(poll_interval) {
+            Ok(HeadsetEvent::Disconnected) => {
+                eprintln!("Device disconnected.");
+                println!("{OFF_ICON}");
+            }
             Ok(_) => {}
-            
+
             Err(DeviceError::UnknownResponse(_, _)) => (),
             Err(DeviceError::UnknownCommand(_)) => (),
             Err(DeviceError::NoResponse()) => (),
 
-            Err(err) => handle_error(err, &mut device),
-
-            
-        }
-
-        match device.headset_connected {
-            Some(connected) => {
-                if !connected {
-                    println!("");
-                    continue;
-                }
-            },
-            None => {continue;}
+            Err(err) => eprintln!("{err}"),
         }
+        let mut state = device.state();
+        let buttons: Vec<u8> = clicks.try_iter().collect();
 
-        let mut mute = false;
-        match stdin_channel.try_recv() {
-            Ok(key) => mute = key.contains("1"),
-            Err(TryRecvError::Disconnected) => panic!("Channel disconnected"),
-            Err(TryRecvError::Empty) => {},
-        }
-        
-        match device.mic_connected {
-            Some(connected) =>{
-                if !connected {
-                    println!(" - {}% ",device.battery_level);
-                    continue;
-                }
-            },
-            None => continue,
-        }
-
-
-        match device.muted {
-            Some(muted) => {
-                if muted {
-                    if mute{
-                        device.mute_mic(false);
+        if state.headset_connected == Some(true) {
+            for button in buttons {
+                if let Some(setting) = config.i3blocks.click.action(button).setting(&state) {
+                    // Later clicks of the batch build on this one, before the headset reports it.
+                    if device.apply(setting).is_ok() {
+                        setting.apply_to(&mut state);
                     }
-                    println!(" - {}% ",device.battery_level);
-                    
-                } else {
-                    if mute{
-                        device.mute_mic(true);
-                    }
-                    println!(" - {}% ",device.battery_level);
                 }
-            },
-            None => {println!(" - {}% ",device.battery_level);},
+            }
         }
 
-
-
+        if let Some(line) = block_line(&template, &state) {
+            println!("{line}");
+        }
     }
 }
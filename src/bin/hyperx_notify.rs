@@ -0,0 +1,121 @@
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+use clap::Parser;
+#[cfg(target_os = "linux")]
+use dbus::blocking::Connection;
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::reconnect::{HeadsetEvent, ReconnectingHeadset};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::notifications::{self, Notifier};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::{DeviceError, DeviceEvent};
+use hyper_x_cloud_ii_core_wireless::DeviceSelector;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Desktop notifications for a HyperX Cloud II Core Wireless headset.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PERCENT",
+        value_delimiter = ',',
+        help = "Battery levels to warn at. Defaults to 20,10,5."
+    )]
+    thresholds: Option<Vec<u8>>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "How far the battery has to recover above a threshold before it warns again. Defaults to 3."
+    )]
+    hysteresis: Option<u8>,
+
+    #[arg(long, help = "Don't notify when the headset disconnects.")]
+    no_disconnect: bool,
+
+    #[arg(long, help = "Don't notify when the microphone is unplugged.")]
+    no_mic_unplugged: bool,
+
+    #[arg(long, help = "Don't notify when the battery is full.")]
+    no_fully_charged: bool,
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    let _ = Args::parse();
+    eprintln!("hyperx_notify sends notifications over D-Bus, which it only does on Linux.");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    let interval = |value| {
+        config::seconds(value).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        })
+    };
+    let retry = interval(config.reconnect_interval);
+    let mut notification_config = config.notifications;
+    if let Some(thresholds) = args.thresholds {
+        notification_config.battery_thresholds = thresholds;
+    }
+    if let Some(hysteresis) = args.hysteresis {
+        notification_config.hysteresis = hysteresis;
+    }
+    notification_config.headset_disconnected &= !args.no_disconnect;
+    notification_config.mic_unplugged &= !args.no_mic_unplugged;
+    notification_config.fully_charged &= !args.no_fully_charged;
+    let mut notifier = Notifier::new(notification_config);
+    let connection = match Connection::new_session() {
+        Ok(connection) => connection,
+        Err(error) => {
+            eprintln!("D-Bus error: {error}");
+            std::process::exit(1);
+        }
+    };
+    let mut headset = ReconnectingHeadset::new(selector, retry);
+    // Kept across a lost dongle, whose notification still names the model.
+    let mut last_state = headset.state();
+
+    // Run loop
+    loop {
+        let (event, state) = match headset.wait_for_updates(Duration::from_secs(10)) {
+            Ok(HeadsetEvent::Device(event)) => (event, headset.state()),
+            // Losing the dongle counts as the headset going away.
+            Ok(HeadsetEvent::Disconnected) => (DeviceEvent::HeadsetConnected(false), last_state.clone()),
+            Ok(HeadsetEvent::Reconnected) => continue,
+            Err(DeviceError::NoResponse()) => continue,
+            Err(DeviceError::UnknownResponse(_, _)) => continue,
+            Err(DeviceError::UnknownCommand(_)) => continue,
+            Err(error) => {
+                eprintln!("{error}");
+                continue;
+            }
+        };
+        last_state = state.clone();
+
+        if let Some(notification) = notifier.handle(&event, &state) {
+            if let Err(error) = notifications::send(&connection, &notification) {
+                eprintln!("D-Bus error: {error}");
+            }
+        }
+    }
+}
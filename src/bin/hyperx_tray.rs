@@ -0,0 +1,101 @@
+#[cfg(target_os = "linux")]
+use std::sync::mpsc;
+
+use clap::Parser;
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::battery_tray::{BatteryTray, TrayHandler};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::history;
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::reconnect::{HeadsetEvent, ReconnectingHeadset};
+#[cfg(target_os = "linux")]
+use hyper_x_cloud_ii_core_wireless::DeviceError;
+use hyper_x_cloud_ii_core_wireless::DeviceSelector;
+
+#[derive(Parser, Debug)]
+#[clap(about = "System tray icon for a HyperX Cloud II Core Wireless headset.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    let _ = Args::parse();
+    eprintln!("hyperx_tray needs a StatusNotifierItem host, which only Linux desktops provide.");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    let interval = |value| {
+        config::seconds(value).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        })
+    };
+    let retry = interval(config.reconnect_interval);
+    let poll_interval = interval(config.tray.poll_interval);
+    let history_path = config.history.path();
+    let (commands, settings) = mpsc::channel();
+    let mut tray = TrayHandler::new(BatteryTray::new(commands));
+    let mut headset = ReconnectingHeadset::new(selector, retry);
+    let mut shown = None;
+
+    // Run loop
+    loop {
+        for setting in settings.try_iter() {
+            if let Err(error) = headset.apply(setting) {
+                eprintln!("{error}");
+            }
+        }
+
+        match headset.wait_for_updates(poll_interval) {
+            Ok(HeadsetEvent::Disconnected) => {
+                tray.set_status("No device found");
+                shown = None;
+                continue;
+            }
+            Ok(_) => {}
+            Err(DeviceError::NoResponse()) => (),
+            Err(DeviceError::UnknownResponse(_, _)) => (),
+            Err(DeviceError::UnknownCommand(_)) => (),
+            Err(error) => {
+                eprintln!("{error}");
+                continue;
+            }
+        }
+        if !headset.is_connected() {
+            continue;
+        }
+
+        let state = headset.state();
+        if shown.as_ref() == Some(&state) {
+            continue;
+        }
+        match state.headset_connected {
+            Some(true) => tray.clear_status(),
+            Some(false) => tray.set_status("Headset disconnected"),
+            None => tray.set_status("Waiting for the headset"),
+        }
+        let estimate = history_path
+            .as_deref()
+            .and_then(|path| history::load(path).ok())
+            .and_then(|samples| history::estimate(&samples, &state, history::now()));
+        tray.update(&state, estimate);
+        shown = Some(state);
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::Parser;
+use hyper_x_cloud_ii_core_wireless::DeviceSelector;
+#[cfg(unix)]
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+#[cfg(unix)]
+use hyper_x_cloud_ii_core_wireless::daemon::{self, Options};
+#[cfg(unix)]
+use hyper_x_cloud_ii_core_wireless::{ipc, Device};
+
+#[derive(Parser, Debug)]
+#[clap(about = "Daemon that owns a HyperX headset and serves hyperx clients over a Unix socket.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Socket to listen on. Defaults to $HYPERXD_SOCKET, then $XDG_RUNTIME_DIR/hyperxd.sock."
+    )]
+    socket: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "SECONDS",
+        value_parser = hyper_x_cloud_ii_core_wireless::config::parse_seconds,
+        help = "How often to re-read every setting from the headset. Defaults to 60."
+    )]
+    refresh_interval: Option<Duration>,
+}
+
+#[cfg(not(unix))]
+fn main() {
+    let _ = Args::parse();
+    eprintln!("hyperxd needs Unix domain sockets, the other binaries open the headset directly here.");
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+fn main() {
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    let socket = args.socket.unwrap_or_else(ipc::socket_path);
+
+    let listener = match daemon::bind(&socket) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("{}: {error}", socket.display());
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Listening on {}", socket.display());
+
+    let mut last_error = None;
+    let open = move || {
+        let result = Device::open(&selector);
+        match &result {
+            Ok(device) => {
+                eprintln!("Opened {}.", device.model().name);
+                last_error = None;
+            }
+            // The worker keeps retrying while the headset is away, only report changes.
+            Err(error) => {
+                let error = error.to_string();
+                if last_error.as_ref() != Some(&error) {
+                    eprintln!("{error}");
+                    last_error = Some(error);
+                }
+            }
+        }
+        result
+    };
+    let interval = |value| {
+        config::seconds(value).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        })
+    };
+    let options = Options {
+        refresh_interval: args.refresh_interval.unwrap_or_else(|| interval(config.daemon.refresh_interval)),
+        reconnect_interval: interval(config.reconnect_interval),
+        restore: config.settings.settings(),
+        history: config.history.path(),
+    };
+
+    if let Err(error) = daemon::serve(listener, open, options) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
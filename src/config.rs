@@ -0,0 +1,465 @@
+//! Settings shared by every binary, read from `$XDG_CONFIG_HOME/hyperx/config.toml`.
+//!
+//! Every key is optional and command line flags win over the file:
+//!
+//! ```toml
+//! device = "serial:ABC123"
+//! reconnect_interval = 1.0
+//!
+//! [monitor]
+//! poll_interval = 10.0
+//!
+//! [i3blocks]
+//! poll_interval = 1.0
+//! template = "{icon} - {battery}%{charging? (charging)}"
+//!
+//! [i3blocks.click]
+//! middle = "toggle_noise_gate"
+//!
+//! [bar]
+//! warning = 25
+//! colors = { critical = "#ff5555" }
+//!
+//! [daemon]
+//! refresh_interval = 60
+//!
+//! [history]
+//! path = "/var/tmp/hyperx-battery.csv"
+//!
+//! [notifications]
+//! battery_thresholds = [20, 10, 5]
+//!
+//! [settings]
+//! mic_monitored = true
+//! monitor_volume = 2
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::history_path;
+use crate::notifications::NotificationConfig;
+use crate::status_bar::ClickActions;
+use crate::template::Template;
+use crate::{Capability, DeviceError, DeviceSelector, DeviceState, Setting};
+
+/// Overrides [`config_path`] when set.
+pub const CONFIG_ENV: &str = "HYPERX_CONFIG";
+
+/// [`CONFIG_ENV`], else `$XDG_CONFIG_HOME/hyperx/config.toml`, else `~/.config/hyperx/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        return Some(path.into());
+    }
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Directory holding the config file and anything else the binaries keep.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("hyperx"))
+}
+
+/// Where named [`Profile`]s are kept, one `<name>.toml` each.
+pub fn profiles_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("profiles"))
+}
+
+fn profile_path(name: &str) -> Result<PathBuf, DeviceError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(DeviceError::InvalidConfig(format!("{name:?} is not a valid profile name")));
+    }
+    profiles_dir()
+        .map(|dir| dir.join(format!("{name}.toml")))
+        .ok_or_else(|| DeviceError::InvalidConfig("no config directory, set $HOME".to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub device: Option<DeviceSelector>,
+    /// Seconds between attempts to reopen a missing headset.
+    pub reconnect_interval: f64,
+    pub monitor: MonitorConfig,
+    pub i3blocks: I3blocksConfig,
+    pub bar: BarConfig,
+    pub tray: TrayConfig,
+    pub daemon: DaemonConfig,
+    pub history: HistoryConfig,
+    pub notifications: NotificationConfig,
+    /// Settings `hyperxd` restores every time the headset connects.
+    pub settings: Profile,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            device: None,
+            reconnect_interval: 1.0,
+            monitor: MonitorConfig::default(),
+            i3blocks: I3blocksConfig::default(),
+            bar: BarConfig::default(),
+            tray: TrayConfig::default(),
+            daemon: DaemonConfig::default(),
+            history: HistoryConfig::default(),
+            notifications: NotificationConfig::default(),
+            settings: Profile::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitorConfig {
+    /// Seconds to wait for a report before redrawing.
+    pub poll_interval: f64,
+    /// [`Template`] to draw instead of the full report.
+    pub template: Option<String>,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        MonitorConfig {
+            poll_interval: 10.0,
+            template: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct I3blocksConfig {
+    pub poll_interval: f64,
+    /// Block text, a [`Template`] with the extra field `icon`, the mic state glyph.
+    pub template: String,
+    /// What clicks on the block do.
+    pub click: ClickActions,
+}
+
+impl Default for I3blocksConfig {
+    fn default() -> Self {
+        I3blocksConfig {
+            poll_interval: 1.0,
+            template: "{icon}{playback_muted? \u{f026}} - \u{f0e7}{battery}% ".to_string(),
+            click: ClickActions::default(),
+        }
+    }
+}
+
+/// The `hyperx_bar` status bar module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarConfig {
+    pub poll_interval: f64,
+    /// [`Template`] for the module text. Empty text hides the module in Waybar.
+    pub template: String,
+    /// [`Template`] for the Waybar tooltip.
+    pub tooltip: String,
+    /// Battery percentage at or below which the module gets the `warning` class.
+    pub warning: u8,
+    /// Battery percentage at or below which the module gets the `critical` class.
+    pub critical: u8,
+    /// Colors by class for i3bar and Polybar, which have no stylesheet. The
+    /// first class of the module with a color wins.
+    pub colors: BTreeMap<String, String>,
+    /// What clicks do on i3bar and swaybar.
+    pub click: ClickActions,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        BarConfig {
+            poll_interval: 1.0,
+            template: "{connected?🎧 {battery}%{charging? ⚡}{muted? 🔇}}".to_string(),
+            tooltip: "{model}\nBattery: {battery|?}%{charging? (charging)}\nMicrophone: {mic_connected?{muted?muted:live}:unplugged}"
+                .to_string(),
+            warning: 20,
+            critical: 10,
+            colors: [("critical", "#ff5555"), ("warning", "#ffb86c"), ("muted", "#888888")]
+                .into_iter()
+                .map(|(class, color)| (class.to_string(), color.to_string()))
+                .collect(),
+            click: ClickActions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrayConfig {
+    pub poll_interval: f64,
+}
+
+impl Default for TrayConfig {
+    fn default() -> Self {
+        TrayConfig { poll_interval: 0.2 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Seconds between full re-reads of the headset state.
+    pub refresh_interval: f64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig { refresh_interval: 60.0 }
+    }
+}
+
+/// Battery history written by `hyperxd` and read for time estimates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    /// Defaults to [`history_path`].
+    pub path: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig { enabled: true, path: None }
+    }
+}
+
+impl HistoryConfig {
+    /// The history file, `None` when disabled.
+    pub fn path(&self) -> Option<PathBuf> {
+        if !self.enabled {
+            return None;
+        }
+        self.path.clone().or_else(history_path)
+    }
+}
+
+/// A set of headset settings; unset fields are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub mic_muted: Option<bool>,
+    pub playback_muted: Option<bool>,
+    pub mic_monitored: Option<bool>,
+    pub monitor_volume: Option<i8>,
+    pub timeout: Option<u8>,
+    pub noise_gate: Option<bool>,
+}
+
+impl Profile {
+    pub fn settings(&self) -> Vec<Setting> {
+        [
+            self.mic_muted.map(Setting::MicMuted),
+            self.playback_muted.map(Setting::PlaybackMuted),
+            self.mic_monitored.map(Setting::MicMonitored),
+            self.monitor_volume.map(Setting::MonitorVolume),
+            self.timeout.map(Setting::Timeout),
+            self.noise_gate.map(Setting::NoiseGate),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// The writable settings of `state`, leaving out what its headset doesn't
+    /// support and everything while it is off, see [`DeviceState::known`].
+    pub fn from_state(state: &DeviceState) -> Self {
+        Profile {
+            mic_muted: state.known(Capability::MicMute, state.muted).flatten(),
+            playback_muted: state.known(Capability::PlaybackMute, state.playback_muted).flatten(),
+            mic_monitored: state.known(Capability::Sidetone, state.mic_monitored).flatten(),
+            monitor_volume: state.known(Capability::SidetoneVolume, state.monitor_volume()),
+            timeout: state.known(Capability::AutoOff, state.timeout),
+            noise_gate: state.known(Capability::NoiseGate, state.noise_gate).flatten(),
+        }
+    }
+
+    /// Settings of this profile that `state` doesn't hold.
+    pub fn mismatches(&self, state: &DeviceState) -> Vec<Setting> {
+        self.settings()
+            .into_iter()
+            .filter(|setting| !setting.is_applied(state))
+            .collect()
+    }
+
+    /// Reads the profile saved as `name` in [`profiles_dir`].
+    pub fn load(name: &str) -> Result<Self, DeviceError> {
+        let path = profile_path(name)?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(DeviceError::NoSuchProfile(name.to_string()))
+            }
+            Err(error) => return Err(DeviceError::InvalidConfig(format!("{}: {error}", path.display()))),
+        };
+        toml::from_str(&text)
+            .map_err(|error| DeviceError::InvalidConfig(format!("{}: {error}", path.display())))
+    }
+
+    /// Writes the profile to [`profiles_dir`] as `name`, replacing any previous one.
+    pub fn save(&self, name: &str) -> Result<PathBuf, DeviceError> {
+        let path = profile_path(name)?;
+        let text = toml::to_string(self).map_err(|error| DeviceError::InvalidConfig(error.to_string()))?;
+        let write = |path: &Path| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(path, text)
+        };
+        write(&path).map_err(|error| DeviceError::InvalidConfig(format!("{}: {error}", path.display())))?;
+        Ok(path)
+    }
+
+    /// Names of the profiles in [`profiles_dir`], sorted.
+    pub fn list() -> Vec<String> {
+        let Some(entries) = profiles_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "toml" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+/// Turns a number of seconds from the file into a [`Duration`], which must be positive.
+pub fn seconds(value: f64) -> Result<Duration, DeviceError> {
+    Duration::try_from_secs_f64(value)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| DeviceError::InvalidConfig(format!("intervals must be a positive number of seconds, not {value}")))
+}
+
+/// Parses a command line interval, like [`seconds`].
+pub fn parse_seconds(text: &str) -> Result<Duration, String> {
+    text.parse()
+        .ok()
+        .and_then(|value| seconds(value).ok())
+        .ok_or_else(|| "expected a positive number of seconds".to_string())
+}
+
+impl Config {
+    /// Loads [`config_path`], or the defaults when there is no such file.
+    pub fn load() -> Result<Self, DeviceError> {
+        match config_path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, DeviceError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| DeviceError::InvalidConfig(format!("{}: {error}", path.display())))?;
+        text.parse()
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = DeviceError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let config: Config =
+            toml::from_str(text).map_err(|error| DeviceError::InvalidConfig(error.to_string()))?;
+        let intervals = [
+            config.reconnect_interval,
+            config.monitor.poll_interval,
+            config.i3blocks.poll_interval,
+            config.bar.poll_interval,
+            config.tray.poll_interval,
+            config.daemon.refresh_interval,
+        ];
+        for interval in intervals {
+            seconds(interval)?;
+        }
+        Template::parse_with(&config.i3blocks.template, &["icon"])?;
+        Template::parse(&config.bar.template)?;
+        Template::parse(&config.bar.tooltip)?;
+        if let Some(template) = &config.monitor.template {
+            Template::parse(template)?;
+        }
+        Ok(config)
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let config: Config = r#"
+        device = "serial:ABC"
+
+        [monitor]
+        poll_interval = 2.5
+
+        [notifications]
+        battery_thresholds = [30, 15]
+
+        [settings]
+        mic_monitored = true
+        monitor_volume = -2
+    "#
+    .parse()
+    .unwrap();
+
+    assert_eq!(config.device, Some(DeviceSelector::Serial("ABC".to_string())));
+    assert_eq!(seconds(config.monitor.poll_interval).unwrap(), Duration::from_millis(2500));
+    assert_eq!(config.i3blocks, I3blocksConfig::default());
+    assert_eq!(config.notifications.battery_thresholds, vec![30, 15]);
+    assert_eq!(config.notifications.hysteresis, 3);
+    assert_eq!(
+        config.settings.settings(),
+        vec![Setting::MicMonitored(true), Setting::MonitorVolume(-2)]
+    );
+
+    assert!("monitor = { poll_interval = -1 }".parse::<Config>().is_err());
+    assert!("reconnect_interval = 0".parse::<Config>().is_err());
+    assert!("daemon = { refresh_interval = nan }".parse::<Config>().is_err());
+    assert!(parse_seconds("0").is_err());
+    assert_eq!(parse_seconds("0.5"), Ok(Duration::from_millis(500)));
+    assert!("unknown = 1".parse::<Config>().is_err());
+}
+
+#[test]
+fn test_profile_from_state() {
+    let mut state = DeviceState {
+        capabilities: crate::MODELS[0].capabilities,
+        headset_connected: Some(true),
+        muted: Some(false),
+        mic_monitored: Some(true),
+        monitor_volume: -2i8 as u8,
+        timeout: 30,
+        noise_gate: Some(true),
+        ..DeviceState::default()
+    };
+    let profile = Profile::from_state(&state);
+    assert_eq!(profile.monitor_volume, Some(-2));
+    assert_eq!(profile.timeout, Some(30));
+    assert!(profile.mismatches(&state).is_empty());
+
+    let text = toml::to_string(&profile).unwrap();
+    assert_eq!(toml::from_str::<Profile>(&text).unwrap(), profile);
+
+    let off = DeviceState {
+        headset_connected: Some(false),
+        ..state.clone()
+    };
+    assert_eq!(Profile::from_state(&off), Profile::default());
+
+    state.timeout = 10;
+    state.mic_monitored = None;
+    assert_eq!(
+        profile.mismatches(&state),
+        vec![Setting::MicMonitored(true), Setting::Timeout(30)]
+    );
+}
@@ -0,0 +1,396 @@
+//! Server side of [`crate::ipc`], run by the `hyperxd` binary.
+//!
+//! A single worker thread owns the [`Device`] and is the only reader of its
+//! reports. Each client connection gets a thread that ships its requests to the
+//! worker as jobs, like [`Device`](crate::asynchronous::Device) does in the async API.
+
+use std::io::{self, BufReader};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::history::History;
+use crate::ipc::{check_owner, read_message, write_message, Request, Response, PROTOCOL_VERSION};
+use crate::{Device, DeviceError, DeviceEvent, DeviceState, Setting};
+
+// How long the worker blocks on a read before checking for queued jobs
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+type Job = Box<dyn FnOnce(Option<&mut Device>) + Send>;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// How often the whole state is re-requested from the headset.
+    pub refresh_interval: Duration,
+    /// How long to wait between attempts to open a missing headset.
+    pub reconnect_interval: Duration,
+    /// Written every time the headset connects, since it comes back with firmware defaults.
+    pub restore: Vec<Setting>,
+    /// Battery history file to append to, see [`crate::history`].
+    pub history: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            refresh_interval: Duration::from_secs(60),
+            reconnect_interval: Duration::from_secs(1),
+            restore: Vec::new(),
+            history: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<DeviceState>,
+    // HID path of the open headset, told to clients so they can check it is the one they want
+    path: Mutex<Option<String>>,
+    subscribers: Mutex<Vec<mpsc::Sender<Response>>>,
+}
+
+impl Shared {
+    fn set_state(&self, state: DeviceState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    fn publish(&self, event: DeviceEvent, state: DeviceState) {
+        self.set_state(state.clone());
+        let response = Response::Event { event, state };
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(response.clone()).is_ok());
+    }
+}
+
+/// Binds `path`, replacing a socket left behind by a daemon that is no longer running.
+/// Anything at `path` that isn't a socket of this user is left alone. The socket
+/// is only accessible to this user.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    check_owner(path)?;
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another hyperxd is listening on {}", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Serves clients on `listener` forever, (re)opening the headset with `open` whenever it is missing.
+pub fn serve<F>(listener: UnixListener, open: F, options: Options) -> io::Result<()>
+where
+    F: FnMut() -> Result<Device, DeviceError> + Send + 'static,
+{
+    let shared = Arc::new(Shared::default());
+    let (jobs, job_receiver) = mpsc::channel::<Job>();
+    {
+        let shared = shared.clone();
+        thread::spawn(move || worker(open, job_receiver, &shared, &options));
+    }
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let jobs = jobs.clone();
+        let shared = shared.clone();
+        // A client hanging up mid-request is its own problem, not the daemon's.
+        thread::spawn(move || {
+            let _ = handle_client(stream, &jobs, &shared);
+        });
+    }
+    Ok(())
+}
+
+fn worker<F>(mut open: F, jobs: mpsc::Receiver<Job>, shared: &Shared, options: &Options)
+where
+    F: FnMut() -> Result<Device, DeviceError>,
+{
+    let mut device: Option<Device> = None;
+    let mut last_refresh = Instant::now();
+    let mut connected = false;
+    let mut history = options.history.clone().and_then(|path| match History::open(path.clone()) {
+        Ok(history) => Some(history),
+        Err(error) => {
+            eprintln!("Battery history disabled, {}: {error}", path.display());
+            None
+        }
+    });
+    let mut record = |state: &DeviceState| {
+        if let Some(recorder) = &mut history {
+            if let Err(error) = recorder.record(state) {
+                eprintln!("Battery history disabled, {}: {error}", recorder.path().display());
+                history = None;
+            }
+        }
+    };
+    loop {
+        let Some(current) = device.as_mut() else {
+            match open() {
+                Ok(opened) => {
+                    shared.set_state(opened.state());
+                    *shared.path.lock().unwrap() = opened.path().map(str::to_string);
+                    device = Some(opened);
+                    last_refresh = Instant::now();
+                    connected = false;
+                }
+                // Nothing to talk to yet: answer jobs with an error while waiting to retry.
+                Err(_) => match jobs.recv_timeout(options.reconnect_interval) {
+                    Ok(job) => job(None),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                },
+            }
+            continue;
+        };
+
+        match jobs.try_recv() {
+            Ok(job) => {
+                job(Some(current));
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) => {}
+        }
+
+        match current.wait_for_updates(POLL_INTERVAL) {
+            Ok(event) => {
+                let state = current.state();
+                shared.publish(event, state.clone());
+                record(&state);
+                // Not every model reports the connection, some are on as long as they answer.
+                let now_connected = state.headset_connected == Some(true);
+                if now_connected && !connected {
+                    restore(current, &options.restore);
+                }
+                connected = now_connected;
+            }
+            Err(DeviceError::NoResponse())
+            | Err(DeviceError::UnknownResponse(_, _))
+            | Err(DeviceError::UnknownCommand(_)) => {}
+            Err(error) => {
+                eprintln!("Lost the headset: {error}");
+                device = None;
+                *shared.path.lock().unwrap() = None;
+                shared.publish(DeviceEvent::HeadsetConnected(false), DeviceState::default());
+                record(&DeviceState {
+                    headset_connected: Some(false),
+                    ..DeviceState::default()
+                });
+                continue;
+            }
+        }
+
+        if last_refresh.elapsed() >= options.refresh_interval {
+            current.sync_state();
+            last_refresh = Instant::now();
+        }
+    }
+}
+
+/// Writes `settings` to a headset that just connected, then reads them back and logs the outcome.
+fn restore(device: &mut Device, settings: &[Setting]) {
+    if settings.is_empty() {
+        return;
+    }
+    for &setting in settings {
+        if let Err(error) = device.apply(setting) {
+            eprintln!("Restoring {setting:?}: {error}");
+        }
+    }
+    // The replies read here are queued as events, so subscribers still see the changes.
+    if let Err(error) = device.refresh_state() {
+        eprintln!("Could not read back the restored settings: {error}");
+        return;
+    }
+    let state = device.state();
+    for setting in settings {
+        if setting.is_applied(&state) {
+            eprintln!("Restored {setting:?}.");
+        } else {
+            eprintln!("{setting:?} did not stick.");
+        }
+    }
+}
+
+/// Runs `f` on the worker thread and waits for its result.
+fn run<T, F>(jobs: &mpsc::Sender<Job>, f: F) -> Result<T, DeviceError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Device) -> Result<T, DeviceError> + Send + 'static,
+{
+    let (reply, response) = mpsc::channel();
+    jobs.send(Box::new(move |device| {
+        let _ = reply.send(device.ok_or(DeviceError::NoDeviceFound()).and_then(f));
+    }))
+    .map_err(|_| DeviceError::WorkerStopped())?;
+    response.recv().map_err(|_| DeviceError::WorkerStopped())?
+}
+
+fn respond<T>(result: Result<T, DeviceError>, ok: impl FnOnce(T) -> Response) -> Response {
+    match result {
+        Ok(value) => ok(value),
+        Err(error) => Response::Error {
+            message: error.to_string(),
+        },
+    }
+}
+
+fn handle_client(stream: UnixStream, jobs: &mpsc::Sender<Job>, shared: &Arc<Shared>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let greeting = match read_message(&mut reader)? {
+        Some(Request::Hello { version }) if version == PROTOCOL_VERSION => Response::Hello {
+            version: PROTOCOL_VERSION,
+            path: shared.path.lock().unwrap().clone(),
+        },
+        Some(Request::Hello { version }) => Response::Error {
+            message: format!("unsupported protocol version {version}, expected {PROTOCOL_VERSION}"),
+        },
+        Some(_) => Response::Error {
+            message: "expected hello".to_string(),
+        },
+        None => return Ok(()),
+    };
+    let accepted = matches!(greeting, Response::Hello { .. });
+    write_message(&mut writer, &greeting)?;
+    if !accepted {
+        return Ok(());
+    }
+
+    while let Some(request) = read_message(&mut reader)? {
+        let response = match request {
+            Request::Hello { .. } => Response::Error {
+                message: "already connected".to_string(),
+            },
+            Request::State => Response::State {
+                state: shared.state.lock().unwrap().clone(),
+            },
+            Request::Refresh => {
+                let shared = shared.clone();
+                let result = run(jobs, move |device| {
+                    device.refresh_state()?;
+                    shared.set_state(device.state());
+                    Ok(device.state())
+                });
+                respond(result, |state| Response::State { state })
+            }
+            Request::Info => respond(run(jobs, |device| device.query_device_info()), |info| {
+                Response::Info { info }
+            }),
+            Request::Set { setting } => respond(run(jobs, move |device| device.apply(setting)), |_| {
+                Response::Done
+            }),
+            Request::Subscribe => {
+                let (subscriber, events) = mpsc::channel();
+                shared.subscribers.lock().unwrap().push(subscriber);
+                write_message(&mut writer, &Response::Done)?;
+                for event in events {
+                    write_message(&mut writer, &event)?;
+                }
+                return Ok(());
+            }
+        };
+        write_message(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_daemon_serves_clients() {
+    use crate::emulator::{Emulator, EMULATOR_PATH};
+    use crate::ipc::Client;
+    use crate::Setting;
+
+    let path = std::env::temp_dir().join(format!("hyperxd-test-{}.sock", std::process::id()));
+    let listener = bind(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    let mut emulator = Some(Emulator::default());
+    thread::spawn(move || {
+        let open = move || {
+            let mut device = Device::with_transport(emulator.take().ok_or(DeviceError::NoDeviceFound())?);
+            device.path = Some(EMULATOR_PATH.to_string());
+            Ok(device)
+        };
+        serve(listener, open, Options::default())
+    });
+
+    let mut client = Client::connect_to(&path).unwrap();
+    client.refresh_state().unwrap();
+    assert_eq!(client.state().battery_level, 80);
+    // Clients are told which headset the daemon has open.
+    assert_eq!(Client::connect_to(&path).unwrap().device_path(), Some(EMULATOR_PATH));
+
+    // Subscribe before changing anything so the echo isn't missed.
+    let _ = client.wait_for_updates(Duration::ZERO);
+    client.apply(Setting::MicMuted(true)).unwrap();
+    while client.wait_for_updates(Duration::from_secs(2)).unwrap() != DeviceEvent::MicMuted(true) {}
+    assert_eq!(client.state().muted, Some(true));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_daemon_restores_settings_on_reconnect() {
+    use crate::emulator::{Emulator, HeadsetState, Script, ScriptEvent};
+    use crate::ipc::Client;
+
+    let path = std::env::temp_dir().join(format!("hyperxd-restore-{}.sock", std::process::id()));
+    let listener = bind(&path).unwrap();
+    // The headset drops out and forgets its monitoring setting while it's away.
+    let script = Script::new()
+        .at(Duration::from_millis(500), ScriptEvent::HeadsetConnected(false))
+        .at(Duration::from_millis(700), ScriptEvent::Monitoring(false))
+        .at(Duration::from_millis(900), ScriptEvent::HeadsetConnected(true));
+    let emulator = Emulator::new(HeadsetState::default(), script);
+    let mut transport = Some(emulator.clone());
+    thread::spawn(move || {
+        let open = move || {
+            transport
+                .take()
+                .map(Device::with_transport)
+                .ok_or(DeviceError::NoDeviceFound())
+        };
+        let options = Options {
+            restore: vec![Setting::MicMonitored(true)],
+            ..Options::default()
+        };
+        serve(listener, open, options)
+    });
+
+    let mut client = Client::connect_to(&path).unwrap();
+    let mut wait_for = |expected: DeviceEvent| {
+        while client.wait_for_updates(Duration::from_secs(2)).unwrap() != expected {}
+    };
+    wait_for(DeviceEvent::HeadsetConnected(false));
+    wait_for(DeviceEvent::HeadsetConnected(true));
+    wait_for(DeviceEvent::MonitoringMic(true));
+    assert!(emulator.headset().monitoring);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_bind_leaves_other_files_alone() {
+    let path = std::env::temp_dir().join(format!("hyperxd-test-{}.txt", std::process::id()));
+    std::fs::write(&path, "keep me").unwrap();
+    assert_eq!(bind(&path).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,337 @@
+//! Session bus service publishing the headset as `org.hyperx.CloudII`.
+//!
+//! Properties mirror [`DeviceState`] and raise `PropertiesChanged` whenever a
+//! report changes them; methods map to the [`Setting`]s of the same name.
+//! The battery is also published at [`UPOWER_PATH`] with the properties of
+//! `org.freedesktop.UPower.Device`. `upowerd` doesn't list it, so only applets
+//! pointed at that path on the session bus see it.
+//! The headset is reached through a [`ReconnectingHeadset`], so the service shares
+//! the dongle with everything else when `hyperxd` is running and survives replugs.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dbus::arg::{Append, Arg, PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::blocking::Connection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::message::{MatchRule, SignalArgs};
+use dbus::Path;
+use dbus_crossroads::{Crossroads, IfaceBuilder, MethodErr};
+
+use crate::reconnect::{HeadsetEvent, ReconnectingHeadset};
+use crate::{DeviceError, DeviceSelector, DeviceState, Setting};
+
+pub const BUS_NAME: &str = "org.hyperx.CloudII";
+pub const OBJECT_PATH: &str = "/org/hyperx/CloudII";
+pub const INTERFACE: &str = "org.hyperx.CloudII";
+pub const UPOWER_PATH: &str = "/org/hyperx/CloudII/battery";
+pub const UPOWER_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+// Values of the UPower Type and State enums
+const UPOWER_TYPE_HEADSET: u32 = 17;
+const UPOWER_STATE_UNKNOWN: u32 = 0;
+const UPOWER_STATE_CHARGING: u32 = 1;
+const UPOWER_STATE_DISCHARGING: u32 = 2;
+const UPOWER_STATE_FULLY_CHARGED: u32 = 4;
+
+// How long one turn of the loop waits for bus messages, then for headset reports
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+type Shared = Arc<Mutex<ReconnectingHeadset>>;
+type Property<T> = (&'static str, fn(&DeviceState) -> T);
+
+// Unknown values are published as false or 0, D-Bus has no null.
+const FLAG_PROPERTIES: [Property<bool>; 5] = [
+    ("HeadsetConnected", |state| state.headset_connected.unwrap_or(false)),
+    ("Charging", |state| state.charging.unwrap_or(false)),
+    ("MicMuted", |state| state.muted.unwrap_or(false)),
+    ("MicConnected", |state| state.mic_connected.unwrap_or(false)),
+    ("Monitoring", |state| state.mic_monitored.unwrap_or(false)),
+];
+
+const VALUE_PROPERTIES: [Property<u8>; 2] = [
+    ("BatteryLevel", |state| state.battery_level),
+    ("Timeout", |state| state.timeout),
+];
+
+// Signed, like the argument of SetMonitorVolume; D-Bus has no signed byte.
+const SIGNED_PROPERTIES: [Property<i16>; 1] = [
+    ("MonitorVolume", |state| i16::from(state.monitor_volume())),
+];
+
+fn upower_state(state: &DeviceState) -> u32 {
+    match (state.headset_connected, state.charging) {
+        (Some(true), Some(true)) if state.battery_level >= 100 => UPOWER_STATE_FULLY_CHARGED,
+        (Some(true), Some(true)) => UPOWER_STATE_CHARGING,
+        (Some(true), Some(false)) => UPOWER_STATE_DISCHARGING,
+        _ => UPOWER_STATE_UNKNOWN,
+    }
+}
+
+const UPOWER_FLAG_PROPERTIES: [Property<bool>; 3] = [
+    ("IsPresent", |state| state.headset_connected == Some(true)),
+    ("IsRechargeable", |_| true),
+    ("PowerSupply", |_| false),
+];
+
+const UPOWER_ENUM_PROPERTIES: [Property<u32>; 2] = [
+    ("Type", |_| UPOWER_TYPE_HEADSET),
+    ("State", upower_state),
+];
+
+const UPOWER_PERCENTAGE_PROPERTIES: [Property<f64>; 1] = [("Percentage", |state| {
+    if state.headset_connected == Some(true) {
+        f64::from(state.battery_level)
+    } else {
+        0.0
+    }
+})];
+
+const UPOWER_TEXT_PROPERTIES: [Property<String>; 3] = [
+    ("Model", |state| state.model.clone()),
+    ("Vendor", |_| "HyperX".to_string()),
+    ("NativePath", |_| "hyperx".to_string()),
+];
+
+fn current_state(shared: &Shared) -> DeviceState {
+    shared.lock().unwrap().state()
+}
+
+fn add_properties<T>(builder: &mut IfaceBuilder<Shared>, properties: &[Property<T>])
+where
+    T: Arg + Append + RefArg + Send + 'static,
+{
+    for &(name, get) in properties {
+        builder
+            .property(name)
+            .get(move |_, shared| Ok(get(&current_state(shared))));
+    }
+}
+
+fn add_changed<T>(changed: &mut PropMap, properties: &[Property<T>], old: &DeviceState, new: &DeviceState)
+where
+    T: PartialEq + RefArg + 'static,
+{
+    for &(name, get) in properties {
+        let value = get(new);
+        if get(old) != value {
+            changed.insert(name.to_string(), Variant(Box::new(value) as Box<dyn RefArg>));
+        }
+    }
+}
+
+/// Properties of [`INTERFACE`] whose value differs between `old` and `new`.
+fn changed_properties(old: &DeviceState, new: &DeviceState) -> PropMap {
+    let mut changed = PropMap::new();
+    add_changed(&mut changed, &FLAG_PROPERTIES, old, new);
+    add_changed(&mut changed, &VALUE_PROPERTIES, old, new);
+    add_changed(&mut changed, &SIGNED_PROPERTIES, old, new);
+    changed
+}
+
+/// Properties of [`UPOWER_INTERFACE`] whose value differs between `old` and `new`.
+fn changed_upower_properties(old: &DeviceState, new: &DeviceState) -> PropMap {
+    let mut changed = PropMap::new();
+    add_changed(&mut changed, &UPOWER_FLAG_PROPERTIES, old, new);
+    add_changed(&mut changed, &UPOWER_ENUM_PROPERTIES, old, new);
+    add_changed(&mut changed, &UPOWER_PERCENTAGE_PROPERTIES, old, new);
+    add_changed(&mut changed, &UPOWER_TEXT_PROPERTIES, old, new);
+    changed
+}
+
+fn apply(shared: &Shared, setting: Setting) -> Result<(), MethodErr> {
+    shared
+        .lock()
+        .unwrap()
+        .apply(setting)
+        .map_err(|error| MethodErr::failed(&error))
+}
+
+fn register_interface(builder: &mut IfaceBuilder<Shared>) {
+    add_properties(builder, &FLAG_PROPERTIES);
+    add_properties(builder, &VALUE_PROPERTIES);
+    add_properties(builder, &SIGNED_PROPERTIES);
+
+    builder.method("MuteMic", ("mute",), (), |_, shared, (mute,): (bool,)| {
+        apply(shared, Setting::MicMuted(mute))
+    });
+    builder.method("MonitorMic", ("monitor",), (), |_, shared, (monitor,): (bool,)| {
+        apply(shared, Setting::MicMonitored(monitor))
+    });
+    builder.method("SetTimeout", ("minutes",), (), |_, shared, (minutes,): (u8,)| {
+        apply(shared, Setting::Timeout(minutes))
+    });
+    builder.method("SetMonitorVolume", ("volume",), (), |_, shared, (volume,): (i16,)| {
+        let volume = i8::try_from(volume)
+            .ok()
+            .filter(|volume| (-5..=5).contains(volume))
+            .ok_or_else(|| MethodErr::invalid_arg("volume must be between -5 and 5"))?;
+        apply(shared, Setting::MonitorVolume(volume))
+    });
+}
+
+fn register_upower_interface(builder: &mut IfaceBuilder<Shared>) {
+    add_properties(builder, &UPOWER_FLAG_PROPERTIES);
+    add_properties(builder, &UPOWER_ENUM_PROPERTIES);
+    add_properties(builder, &UPOWER_PERCENTAGE_PROPERTIES);
+    add_properties(builder, &UPOWER_TEXT_PROPERTIES);
+
+    builder.method("Refresh", (), (), |_, shared: &mut Shared, ()| {
+        shared
+            .lock()
+            .unwrap()
+            .refresh_state()
+            .map_err(|error| MethodErr::failed(&error))
+    });
+}
+
+fn properties_changed(interface: &str, path: &Path<'static>, changed: PropMap) -> dbus::Message {
+    PropertiesPropertiesChanged {
+        interface_name: interface.to_string(),
+        changed_properties: changed,
+        invalidated_properties: Vec::new(),
+    }
+    .to_emit_message(path)
+}
+
+/// Serves the headset objects on `connection`, without claiming a name.
+fn export(connection: &Connection, shared: &Shared) {
+    let mut crossroads = Crossroads::new();
+    let interface = crossroads.register(INTERFACE, register_interface);
+    let upower_interface = crossroads.register(UPOWER_INTERFACE, register_upower_interface);
+    crossroads.insert(OBJECT_PATH, &[interface], shared.clone());
+    crossroads.insert(UPOWER_PATH, &[upower_interface], shared.clone());
+    connection.start_receive(
+        MatchRule::new_method_call(),
+        Box::new(move |message, connection| {
+            let _ = crossroads.handle_message(message, connection);
+            true
+        }),
+    );
+}
+
+/// Claims [`BUS_NAME`] on the session bus and serves the headset picked by `selector` forever,
+/// retrying after `reopen_interval`, then less and less often, while it is missing.
+pub fn serve(selector: &DeviceSelector, reopen_interval: Duration) -> Result<(), dbus::Error> {
+    let connection = Connection::new_session()?;
+    connection.request_name(BUS_NAME, false, true, false)?;
+
+    let shared: Shared = Arc::new(Mutex::new(ReconnectingHeadset::new(selector.clone(), reopen_interval)));
+    export(&connection, &shared);
+
+    let path = Path::from(OBJECT_PATH);
+    let upower_path = Path::from(UPOWER_PATH);
+    let mut published = DeviceState::default();
+    loop {
+        connection.process(POLL_INTERVAL)?;
+
+        match shared.lock().unwrap().wait_for_updates(POLL_INTERVAL) {
+            Ok(HeadsetEvent::Disconnected) => eprintln!("Lost the headset."),
+            Ok(_)
+            | Err(DeviceError::NoResponse())
+            | Err(DeviceError::UnknownResponse(_, _))
+            | Err(DeviceError::UnknownCommand(_)) => {}
+            Err(error) => eprintln!("{error}"),
+        }
+
+        let state = current_state(&shared);
+        if state != published {
+            let changed = changed_properties(&published, &state);
+            if !changed.is_empty() {
+                let _ = connection.send(properties_changed(INTERFACE, &path, changed));
+            }
+            let changed = changed_upower_properties(&published, &state);
+            if !changed.is_empty() {
+                let _ = connection.send(properties_changed(UPOWER_INTERFACE, &upower_path, changed));
+            }
+            published = state;
+        }
+    }
+}
+
+#[test]
+fn test_changed_properties() {
+    let old = DeviceState {
+        battery_level: 80,
+        muted: Some(false),
+        ..DeviceState::default()
+    };
+    let new = DeviceState {
+        battery_level: 75,
+        ..old.clone()
+    };
+
+    let changed = changed_properties(&old, &new);
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed["BatteryLevel"].0.as_u64(), Some(75));
+    assert!(changed_properties(&new, &new).is_empty());
+
+    let quiet = DeviceState {
+        monitor_volume: -3i8 as u8,
+        ..new.clone()
+    };
+    assert_eq!(changed_properties(&new, &quiet)["MonitorVolume"].0.as_i64(), Some(-3));
+}
+
+#[test]
+fn test_upower_state() {
+    let mut state = DeviceState {
+        headset_connected: Some(true),
+        charging: Some(false),
+        battery_level: 60,
+        ..DeviceState::default()
+    };
+    assert_eq!(upower_state(&state), UPOWER_STATE_DISCHARGING);
+    state.charging = Some(true);
+    assert_eq!(upower_state(&state), UPOWER_STATE_CHARGING);
+    state.battery_level = 100;
+    assert_eq!(upower_state(&state), UPOWER_STATE_FULLY_CHARGED);
+    state.headset_connected = Some(false);
+    assert_eq!(upower_state(&state), UPOWER_STATE_UNKNOWN);
+}
+
+#[test]
+fn test_upower_object() {
+    use dbus::arg::prop_cast;
+    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+
+    use crate::emulator::{Emulator, HeadsetState, Script};
+    use crate::headset::Headset;
+    use crate::Device;
+
+    // Run under `dbus-run-session` to get a private bus.
+    let Ok(server) = Connection::new_session() else {
+        eprintln!("No session bus, skipping.");
+        return;
+    };
+    let emulator = Emulator::new(
+        HeadsetState {
+            battery_level: 70,
+            ..HeadsetState::default()
+        },
+        Script::new(),
+    );
+    let open = move || Ok(Headset::Direct(Device::with_transport(emulator.clone())));
+    let shared: Shared = Arc::new(Mutex::new(ReconnectingHeadset::with_opener(open, POLL_INTERVAL)));
+    while current_state(&shared).charging.is_none() {
+        let _ = shared.lock().unwrap().wait_for_updates(POLL_INTERVAL);
+    }
+    export(&server, &shared);
+    let name = server.unique_name().to_string();
+    std::thread::spawn(move || loop {
+        let _ = server.process(POLL_INTERVAL);
+    });
+
+    let client = Connection::new_session().unwrap();
+    let properties = client
+        .with_proxy(name, UPOWER_PATH, Duration::from_secs(5))
+        .get_all(UPOWER_INTERFACE)
+        .unwrap();
+    assert_eq!(prop_cast::<f64>(&properties, "Percentage"), Some(&70.0));
+    assert_eq!(prop_cast::<u32>(&properties, "State"), Some(&UPOWER_STATE_DISCHARGING));
+    assert_eq!(prop_cast::<u32>(&properties, "Type"), Some(&UPOWER_TYPE_HEADSET));
+    assert_eq!(prop_cast::<bool>(&properties, "IsPresent"), Some(&true));
+    assert_eq!(prop_cast::<bool>(&properties, "PowerSupply"), Some(&false));
+    assert_eq!(prop_cast::<String>(&properties, "Vendor").map(String::as_str), Some("HyperX"));
+}
@@ -0,0 +1,549 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hidapi::HidError;
+
+use crate::{FirmwareVersion, ReportByte, Transport, MAGIC_BYTE};
+
+/// Environment variable that makes [`Device::new`](crate::Device::new) open an [`Emulator`]
+/// instead of the dongle. Its value is a script, a path to a script file, or `1` for the demo script.
+pub const EMULATOR_ENV: &str = "HYPERX_EMULATOR";
+
+/// Path reported by [`Device::enumerate`](crate::Device::enumerate) for the emulated headset.
+pub const EMULATOR_PATH: &str = "emulator";
+
+const DEMO_SCRIPT: &str = "5s battery=75; 10s charging=true; 15s battery=80; 20s charging=false; \
+     30s mic=false; 35s mic=true; 40s mute=true; 45s mute=false; 50s headset=false; 55s headset=true";
+
+/// Settings and sensors of the simulated headset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadsetState {
+    pub connected: bool,
+    pub battery_level: u8,
+    pub charging: bool,
+    pub mic_connected: bool,
+    pub mic_muted: bool,
+    pub monitoring: bool,
+    pub monitor_volume: u8,
+    pub timeout: u8,
+    pub noise_gate: bool,
+    pub playback_muted: bool,
+    pub firmware_version: FirmwareVersion,
+    pub hardware_revision: u8,
+    pub serial_number: String,
+}
+
+impl Default for HeadsetState {
+    fn default() -> Self {
+        HeadsetState {
+            connected: true,
+            battery_level: 80,
+            charging: false,
+            mic_connected: true,
+            mic_muted: false,
+            monitoring: false,
+            monitor_volume: 0,
+            timeout: 20,
+            noise_gate: false,
+            playback_muted: false,
+            firmware_version: FirmwareVersion { major: 1, minor: 0 },
+            hardware_revision: 1,
+            serial_number: "EMULATED0001".to_string(),
+        }
+    }
+}
+
+/// Something that happens to the headset on its own, reported with an `Update*` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptEvent {
+    Battery(u8),
+    Charging(bool),
+    MicPlugged(bool),
+    MicMuted(bool),
+    Monitoring(bool),
+    HeadsetConnected(bool),
+    /// The dongle is pulled: every read and write fails until `Replug`.
+    Unplug,
+    Replug,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// Fires once this much time has passed since the emulator was created.
+    At(Duration),
+    /// Fires right after the host has written this many reports.
+    AfterWrites(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptStep {
+    pub trigger: Trigger,
+    pub event: ScriptEvent,
+}
+
+/// Ordered list of [`ScriptStep`]s played back by an [`Emulator`].
+///
+/// The text form is a `;` or newline separated list of `<trigger> <event>` entries,
+/// e.g. `"5s battery=70; 10s charging=true; w3 unplug"`. Triggers are `<n>s`, `<n>ms`
+/// or `w<n>` (after n writes); events are `battery=<0-100>`, `charging=`, `mic=`,
+/// `mute=`, `monitor=`, `headset=` with a boolean, `unplug` and `replug`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub steps: Vec<ScriptStep>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The script used when [`EMULATOR_ENV`] is set to `1`.
+    pub fn demo() -> Self {
+        DEMO_SCRIPT.parse().unwrap()
+    }
+
+    pub fn at(mut self, time: Duration, event: ScriptEvent) -> Self {
+        self.steps.push(ScriptStep {
+            trigger: Trigger::At(time),
+            event,
+        });
+        self
+    }
+
+    pub fn after_writes(mut self, writes: usize, event: ScriptEvent) -> Self {
+        self.steps.push(ScriptStep {
+            trigger: Trigger::AfterWrites(writes),
+            event,
+        });
+        self
+    }
+
+    /// Drops the battery by one percent every `every`, starting at `from` and stopping at `to`.
+    pub fn battery_drain(mut self, from: u8, to: u8, every: Duration) -> Self {
+        for (step, level) in (to..from).rev().enumerate() {
+            self = self.at(every * (step as u32 + 1), ScriptEvent::Battery(level));
+        }
+        self
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected a boolean, got {value:?}")),
+    }
+}
+
+impl FromStr for ScriptStep {
+    type Err = String;
+
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        let (trigger, event) = entry
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected \"<trigger> <event>\", got {entry:?}"))?;
+
+        let number = |digits: &str| {
+            digits
+                .parse::<u64>()
+                .map_err(|_| format!("invalid trigger {trigger:?}"))
+        };
+        let trigger = if let Some(writes) = trigger.strip_prefix('w') {
+            Trigger::AfterWrites(number(writes)? as usize)
+        } else if let Some(millis) = trigger.strip_suffix("ms") {
+            Trigger::At(Duration::from_millis(number(millis)?))
+        } else if let Some(secs) = trigger.strip_suffix('s') {
+            Trigger::At(Duration::from_secs(number(secs)?))
+        } else {
+            return Err(format!("invalid trigger {trigger:?}"));
+        };
+
+        let event = event.trim();
+        let event = match event.split_once('=') {
+            None if event == "unplug" => ScriptEvent::Unplug,
+            None if event == "replug" => ScriptEvent::Replug,
+            Some(("battery", level)) => ScriptEvent::Battery(
+                level
+                    .parse()
+                    .ok()
+                    .filter(|level| *level <= 100)
+                    .ok_or_else(|| format!("invalid battery level {level:?}"))?,
+            ),
+            Some(("charging", value)) => ScriptEvent::Charging(parse_bool(value)?),
+            Some(("mic", value)) => ScriptEvent::MicPlugged(parse_bool(value)?),
+            Some(("mute", value)) => ScriptEvent::MicMuted(parse_bool(value)?),
+            Some(("monitor", value)) => ScriptEvent::Monitoring(parse_bool(value)?),
+            Some(("headset", value)) => ScriptEvent::HeadsetConnected(parse_bool(value)?),
+            _ => return Err(format!("unknown event {event:?}")),
+        };
+
+        Ok(ScriptStep { trigger, event })
+    }
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let steps = script
+            .split([';', '\n'])
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Script { steps })
+    }
+}
+
+#[derive(Debug)]
+struct EmulatorState {
+    headset: HeadsetState,
+    outbox: VecDeque<[u8; 8]>,
+    pending: Vec<ScriptStep>,
+    started: Instant,
+    writes: usize,
+    unplugged: bool,
+}
+
+impl EmulatorState {
+    fn report(&mut self, command: ReportByte, value: u8) {
+        let mut report = [0u8; 8];
+        report[0] = MAGIC_BYTE;
+        report[1] = command as u8;
+        match command {
+            ReportByte::UpdateBatteryStatus | ReportByte::GetBatteryLevel => report[4] = value,
+            _ => report[2] = value,
+        }
+        self.outbox.push_back(report);
+    }
+
+    fn apply(&mut self, event: ScriptEvent) {
+        match event {
+            ScriptEvent::Unplug => {
+                self.unplugged = true;
+                self.outbox.clear();
+                return;
+            }
+            ScriptEvent::Replug => {
+                self.unplugged = false;
+                return;
+            }
+            _ if self.unplugged => return,
+            _ => {}
+        }
+
+        let headset = &mut self.headset;
+        let (command, value) = match event {
+            ScriptEvent::Battery(level) => {
+                headset.battery_level = level;
+                (ReportByte::UpdateBatteryStatus, level)
+            }
+            ScriptEvent::Charging(charging) => {
+                headset.charging = charging;
+                (ReportByte::UpdateChargingStatus, charging as u8)
+            }
+            ScriptEvent::MicPlugged(plugged) => {
+                headset.mic_connected = plugged;
+                (ReportByte::UpdateMicConnectionStatus, plugged as u8)
+            }
+            ScriptEvent::MicMuted(muted) => {
+                headset.mic_muted = muted;
+                (ReportByte::UpdateMicMuteStatus, muted as u8)
+            }
+            ScriptEvent::Monitoring(monitoring) => {
+                headset.monitoring = monitoring;
+                (ReportByte::UpdateMicMonitorStatus, monitoring as u8)
+            }
+            ScriptEvent::HeadsetConnected(connected) => {
+                headset.connected = connected;
+                (ReportByte::UpdateConnectedStatus, connected as u8)
+            }
+            ScriptEvent::Unplug | ScriptEvent::Replug => unreachable!(),
+        };
+        self.report(command, value);
+    }
+
+    /// Applies every step whose trigger has been reached.
+    fn fire_due(&mut self) {
+        let elapsed = self.started.elapsed();
+        let writes = self.writes;
+        let (due, pending): (Vec<_>, Vec<_>) =
+            self.pending.drain(..).partition(|step| match step.trigger {
+                Trigger::At(time) => time <= elapsed,
+                Trigger::AfterWrites(count) => count <= writes,
+            });
+        self.pending = pending;
+        for step in due {
+            self.apply(step.event);
+        }
+    }
+
+    fn next_timed_step(&self) -> Option<Duration> {
+        self.pending
+            .iter()
+            .filter_map(|step| match step.trigger {
+                Trigger::At(time) => Some(time),
+                Trigger::AfterWrites(_) => None,
+            })
+            .min()
+    }
+
+    fn handle_request(&mut self, data: &[u8]) {
+        let (Some(&MAGIC_BYTE), Some(&command)) = (data.first(), data.get(1)) else {
+            return;
+        };
+        let Ok(command) = ReportByte::try_from(command) else {
+            return;
+        };
+        let arg = data.get(2).copied().unwrap_or(0);
+
+        if command == ReportByte::GetConnectedStatus {
+            let connected = self.headset.connected;
+            self.report(command, connected as u8);
+            return;
+        }
+        // The dongle stays silent for everything else while the headset is off.
+        if !self.headset.connected {
+            return;
+        }
+
+        if command == ReportByte::GetDeviceInformation {
+            let headset = &self.headset;
+            let mut report = [0u8; 8];
+            report[0] = MAGIC_BYTE;
+            report[1] = command as u8;
+            report[2] = headset.firmware_version.major;
+            report[3] = headset.firmware_version.minor;
+            report[4] = headset.hardware_revision;
+            self.outbox.push_back(report);
+            return;
+        }
+
+        let headset = &mut self.headset;
+        let value = match command {
+            ReportByte::SetMonitorState => {
+                headset.monitoring = arg == 1;
+                arg
+            }
+            ReportByte::SetPowerAutoOffTiming => {
+                headset.timeout = arg;
+                arg
+            }
+            ReportByte::SetMicMuteState => {
+                headset.mic_muted = arg == 1;
+                arg
+            }
+            ReportByte::SetPlaybackMuteState => {
+                headset.playback_muted = arg == 1;
+                arg
+            }
+            ReportByte::SetMonitorVolume => {
+                headset.monitor_volume = arg;
+                arg
+            }
+            ReportByte::SetNoiseGateState => {
+                headset.noise_gate = arg == 1;
+                arg
+            }
+            ReportByte::GetMonitorState => headset.monitoring as u8,
+            ReportByte::GetPowerAutoOffTiming => headset.timeout,
+            ReportByte::GetMicMuteState => headset.mic_muted as u8,
+            ReportByte::GetPlaybackMuteState => headset.playback_muted as u8,
+            ReportByte::GetMonitorVolume => headset.monitor_volume,
+            ReportByte::GetBatteryLevel => headset.battery_level,
+            ReportByte::GetChargerState => headset.charging as u8,
+            ReportByte::GetMicPlugState => headset.mic_connected as u8,
+            ReportByte::GetNoiseGateState => headset.noise_gate as u8,
+            // Update reports only ever travel from the headset to the host.
+            _ => return,
+        };
+        self.report(command, value);
+    }
+}
+
+fn disconnected() -> HidError {
+    HidError::HidApiError {
+        message: "device disconnected".to_string(),
+    }
+}
+
+/// Software model of a Cloud II Core Wireless dongle and headset.
+///
+/// Answers queries with 8-byte [`MAGIC_BYTE`] reports and plays back a [`Script`]
+/// of unsolicited updates. Clones share the same headset, so tests can keep a
+/// handle to inspect or poke it while a [`Device`](crate::Device) owns another.
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    state: Arc<Mutex<EmulatorState>>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new(HeadsetState::default(), Script::new())
+    }
+}
+
+impl Emulator {
+    pub fn new(headset: HeadsetState, script: Script) -> Self {
+        Emulator {
+            state: Arc::new(Mutex::new(EmulatorState {
+                headset,
+                outbox: VecDeque::new(),
+                pending: script.steps,
+                started: Instant::now(),
+                writes: 0,
+                unplugged: false,
+            })),
+        }
+    }
+
+    /// Builds an emulator from the value of [`EMULATOR_ENV`].
+    pub fn from_env_value(value: &str) -> Result<Self, String> {
+        let script = match value.trim() {
+            "" | "1" => Script::demo(),
+            value if std::path::Path::new(value).is_file() => std::fs::read_to_string(value)
+                .map_err(|err| format!("{value}: {err}"))?
+                .parse()?,
+            value => value.parse()?,
+        };
+        Ok(Self::new(HeadsetState::default(), script))
+    }
+
+    /// Snapshot of the simulated headset.
+    pub fn headset(&self) -> HeadsetState {
+        self.state.lock().unwrap().headset.clone()
+    }
+
+    /// Applies an event right away, as if a script step had fired.
+    pub fn inject(&self, event: ScriptEvent) {
+        self.state.lock().unwrap().apply(event);
+    }
+
+    pub fn writes(&self) -> usize {
+        self.state.lock().unwrap().writes
+    }
+}
+
+impl Transport for Emulator {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        let mut state = self.state.lock().unwrap();
+        state.fire_due();
+        if state.unplugged {
+            return Err(disconnected());
+        }
+        state.writes += 1;
+        state.handle_request(data);
+        state.fire_due();
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+        let deadline = Instant::now() + Duration::from_millis(timeout.max(0) as u64);
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                state.fire_due();
+                if state.unplugged {
+                    return Err(disconnected());
+                }
+                if let Some(report) = state.outbox.pop_front() {
+                    let len = report.len().min(buf.len());
+                    buf[..len].copy_from_slice(&report[..len]);
+                    return Ok(len);
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(0);
+                }
+                let until_step = state
+                    .next_timed_step()
+                    .map(|time| (state.started + time).saturating_duration_since(now));
+                match until_step {
+                    Some(until_step) => until_step.min(deadline - now),
+                    None => deadline - now,
+                }
+            };
+            std::thread::sleep(wait);
+        }
+    }
+
+    fn product(&self) -> Result<Option<String>, HidError> {
+        Ok(Some("HyperX Cloud II Core Wireless (emulated)".to_string()))
+    }
+
+    fn manufacturer(&self) -> Result<Option<String>, HidError> {
+        Ok(Some("HP, Inc".to_string()))
+    }
+
+    fn serial_number(&self) -> Result<Option<String>, HidError> {
+        Ok(Some(self.state.lock().unwrap().headset.serial_number.clone()))
+    }
+}
+
+#[test]
+fn test_emulator_answers_sync_state() {
+    use crate::Device;
+
+    let emulator = Emulator::default();
+    let mut device = Device::with_transport(emulator.clone());
+    while device.wait_for_updates(Duration::ZERO).is_ok() {}
+
+    let headset = emulator.headset();
+    assert_eq!(device.headset_connected, Some(true));
+    assert_eq!(device.battery_level, headset.battery_level);
+    assert_eq!(device.charging, Some(headset.charging));
+    assert_eq!(device.mic_connected, Some(headset.mic_connected));
+    assert_eq!(device.muted, Some(headset.mic_muted));
+    assert_eq!(device.mic_monitored, Some(headset.monitoring));
+    assert_eq!(device.timeout, headset.timeout);
+    assert_eq!(device.noise_gate, Some(headset.noise_gate));
+    assert_eq!(device.playback_muted, Some(headset.playback_muted));
+
+    let info = device.query_device_info().unwrap();
+    assert_eq!(info.firmware_version, Some(headset.firmware_version));
+    assert_eq!(info.serial_number, Some(headset.serial_number));
+
+    device.mute_mic(true).unwrap();
+    while device.wait_for_updates(Duration::ZERO).is_ok() {}
+    assert_eq!(device.muted, Some(true));
+    assert!(emulator.headset().mic_muted);
+}
+
+#[test]
+fn test_emulator_script() {
+    use crate::{Device, DeviceError, DeviceEvent};
+
+    let script: Script = "0s charging=true; w3 unplug".parse().unwrap();
+    let emulator = Emulator::new(HeadsetState::default(), script);
+
+    // The dongle disappears after the third sync_state query.
+    let mut device = Device::with_transport(emulator.clone());
+    assert!(matches!(
+        device.wait_for_updates(Duration::ZERO),
+        Err(DeviceError::HidError(_))
+    ));
+    assert_eq!(emulator.writes(), 3);
+
+    emulator.inject(ScriptEvent::Replug);
+    device.sync_state();
+    let mut events = Vec::new();
+    while let Ok(event) = device.wait_for_updates(Duration::ZERO) {
+        events.push(event);
+    }
+    assert_eq!(events.len(), 11);
+    assert!(matches!(events[0], DeviceEvent::HeadsetConnected(true)));
+    assert!(matches!(events[1], DeviceEvent::DeviceInformation(_, _)));
+    assert_eq!(device.charging, Some(true));
+
+    assert!("5x battery=1".parse::<Script>().is_err());
+    assert!("5s battery=101".parse::<Script>().is_err());
+    assert_eq!(
+        Script::new()
+            .battery_drain(80, 78, Duration::from_secs(1))
+            .steps
+            .len(),
+        2
+    );
+}
@@ -0,0 +1,80 @@
+//! [`Headset`], what the front-ends talk to: `hyperxd` where it can run, the
+//! dongle itself otherwise.
+
+use std::time::Duration;
+
+#[cfg(unix)]
+use crate::ipc::Client;
+use crate::{Device, DeviceError, DeviceEvent, DeviceInfo, DeviceSelector, DeviceState, Setting};
+
+/// A headset reached through `hyperxd` when it is running, or opened directly otherwise.
+/// There is no daemon on platforms without Unix sockets.
+pub enum Headset {
+    #[cfg(unix)]
+    Daemon(Client),
+    Direct(Device),
+}
+
+impl Headset {
+    /// Connects to the daemon if it serves the headset picked by `selector`, and
+    /// opens that headset directly otherwise, e.g. while the daemon serves another
+    /// one or none at all.
+    pub fn open(selector: &DeviceSelector) -> Result<Self, DeviceError> {
+        #[cfg(unix)]
+        if let Ok(client) = Client::connect() {
+            if client.device_path().is_some_and(|path| selects(selector, path)) {
+                return Ok(Headset::Daemon(client));
+            }
+        }
+        Device::open(selector).map(Headset::Direct)
+    }
+
+    pub fn state(&self) -> DeviceState {
+        match self {
+            #[cfg(unix)]
+            Headset::Daemon(client) => client.state().clone(),
+            Headset::Direct(device) => device.state(),
+        }
+    }
+
+    pub fn wait_for_updates(&mut self, duration: Duration) -> Result<DeviceEvent, DeviceError> {
+        match self {
+            #[cfg(unix)]
+            Headset::Daemon(client) => client.wait_for_updates(duration),
+            Headset::Direct(device) => device.wait_for_updates(duration),
+        }
+    }
+
+    pub fn refresh_state(&mut self) -> Result<(), DeviceError> {
+        match self {
+            #[cfg(unix)]
+            Headset::Daemon(client) => client.refresh_state(),
+            Headset::Direct(device) => device.refresh_state(),
+        }
+    }
+
+    pub fn device_info(&mut self) -> Result<DeviceInfo, DeviceError> {
+        match self {
+            #[cfg(unix)]
+            Headset::Daemon(client) => client.device_info(),
+            Headset::Direct(device) => device.query_device_info(),
+        }
+    }
+
+    pub fn apply(&mut self, setting: Setting) -> Result<(), DeviceError> {
+        match self {
+            #[cfg(unix)]
+            Headset::Daemon(client) => client.apply(setting),
+            Headset::Direct(device) => device.apply(setting).map(|_| ()),
+        }
+    }
+}
+
+/// Whether `selector` picks the attached headset at `path`.
+#[cfg(unix)]
+fn selects(selector: &DeviceSelector, path: &str) -> bool {
+    Device::enumerate()
+        .ok()
+        .and_then(|descriptors| selector.select(&descriptors).map(|descriptor| descriptor.path == path))
+        .unwrap_or(false)
+}
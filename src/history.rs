@@ -0,0 +1,302 @@
+//! Battery history recorded by `hyperxd`, and the charge and discharge rates
+//! estimated from it.
+//!
+//! The history is a CSV file with a row each time the battery level, the
+//! charging state or the connection changes: `time,connected,battery,charging`,
+//! the time in seconds since the Unix epoch. Nothing is seen while the daemon
+//! isn't running, so it starts with a disconnected row. Only the last
+//! [`MAX_ROWS`] rows are kept.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::DeviceState;
+
+/// The file is cut back to half of this once it grows past it.
+pub const MAX_ROWS: usize = 2000;
+
+const HEADER: &str = "time,connected,battery,charging";
+// Shorter stretches mostly measure how the reported level is rounded
+const MIN_SPAN: Duration = Duration::from_secs(10 * 60);
+// Older samples describe another use of the headset, if not another battery
+const MAX_AGE: Duration = Duration::from_secs(6 * 3600);
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// `$XDG_STATE_HOME/hyperx/battery.csv`, else `~/.local/state/hyperx/battery.csv`.
+pub fn history_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("hyperx").join("battery.csv"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub connected: bool,
+    /// 0 while disconnected.
+    pub battery: u8,
+    pub charging: bool,
+}
+
+impl Sample {
+    /// The sample for `state` at `time`, or `None` until the headset has told
+    /// whether it is on and, if it is, its battery level.
+    pub fn from_state(state: &DeviceState, time: u64) -> Option<Self> {
+        match state.headset_connected? {
+            // The level reads 0 until the headset answers, and it is off for good at 0% anyway.
+            true if state.battery_level == 0 || state.charging.is_none() => None,
+            true => Some(Sample {
+                time,
+                connected: true,
+                battery: state.battery_level,
+                charging: state.charging == Some(true),
+            }),
+            false => Some(Sample {
+                time,
+                connected: false,
+                battery: 0,
+                charging: false,
+            }),
+        }
+    }
+
+    fn same_reading(&self, other: &Sample) -> bool {
+        (self.connected, self.battery, self.charging) == (other.connected, other.battery, other.charging)
+    }
+
+    fn to_csv(self) -> String {
+        format!("{},{},{},{}", self.time, self.connected, self.battery, self.charging)
+    }
+
+    fn from_csv(line: &str) -> Option<Self> {
+        let mut fields = line.trim().split(',');
+        let sample = Sample {
+            time: fields.next()?.parse().ok()?,
+            connected: fields.next()?.parse().ok()?,
+            battery: fields.next()?.parse().ok()?,
+            charging: fields.next()?.parse().ok()?,
+        };
+        fields.next().is_none().then_some(sample)
+    }
+}
+
+/// Appends a [`Sample`] to the history file whenever the reading changes.
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    last: Option<Sample>,
+    rows: usize,
+}
+
+impl History {
+    /// Opens the history at `path`, recording a disconnect to cut off whatever
+    /// was recorded before: the headset may have been used or charged since.
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let rows = match load(&path) {
+            Ok(samples) => samples.len(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        let mut history = History { path, last: None, rows };
+        history.append(Sample {
+            time: now(),
+            connected: false,
+            battery: 0,
+            charging: false,
+        })?;
+        Ok(history)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records `state` unless it reads the same as the last recorded sample.
+    pub fn record(&mut self, state: &DeviceState) -> io::Result<()> {
+        let Some(sample) = Sample::from_state(state, now()) else {
+            return Ok(());
+        };
+        if self.last.is_some_and(|last| last.same_reading(&sample)) {
+            return Ok(());
+        }
+        self.append(sample)
+    }
+
+    fn append(&mut self, sample: Sample) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{HEADER}")?;
+        }
+        writeln!(file, "{}", sample.to_csv())?;
+        self.last = Some(sample);
+        self.rows += 1;
+        if self.rows > MAX_ROWS {
+            self.trim()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the file with its newest `MAX_ROWS / 2` rows.
+    fn trim(&mut self) -> io::Result<()> {
+        let samples = load(&self.path)?;
+        let kept = &samples[samples.len().saturating_sub(MAX_ROWS / 2)..];
+        let mut text = format!("{HEADER}\n");
+        for sample in kept {
+            text += &sample.to_csv();
+            text.push('\n');
+        }
+        // Readers never see a half-written file.
+        let temporary = self.path.with_extension("csv.tmp");
+        std::fs::write(&temporary, text)?;
+        std::fs::rename(&temporary, &self.path)?;
+        self.rows = kept.len();
+        Ok(())
+    }
+}
+
+/// Reads the samples of a history file, skipping lines that aren't one.
+pub fn load(path: &Path) -> io::Result<Vec<Sample>> {
+    Ok(std::fs::read_to_string(path)?.lines().filter_map(Sample::from_csv).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Percent per hour, negative while discharging.
+    pub rate: f64,
+    /// Until the battery is empty, while discharging.
+    pub time_remaining: Option<Duration>,
+    /// Until the battery is full, while charging.
+    pub time_to_full: Option<Duration>,
+}
+
+/// Estimates the current charge or discharge rate from the stretch of
+/// `samples` the headset has spent connected and charging, or not, like in
+/// `state`, over the last few hours before `now`. `None` until that stretch
+/// spans a few level changes.
+pub fn estimate(samples: &[Sample], state: &DeviceState, now: u64) -> Option<Estimate> {
+    if state.headset_connected != Some(true) {
+        return None;
+    }
+    let charging = state.charging?;
+    let oldest = now.saturating_sub(MAX_AGE.as_secs());
+    let start = samples
+        .iter()
+        .rposition(|sample| !sample.connected || sample.charging != charging || sample.time < oldest)
+        .map_or(0, |index| index + 1);
+    let stretch = &samples[start..];
+
+    // A level change is the only moment the level is known exactly, the
+    // first sample of the stretch may have been taken anywhere within a level.
+    let changes: Vec<&Sample> = stretch
+        .windows(2)
+        .filter(|pair| pair[0].battery != pair[1].battery)
+        .map(|pair| &pair[1])
+        .collect();
+    let (first, last) = match changes.as_slice() {
+        [] => return None,
+        [only] => (stretch.first()?, *only),
+        [first, .., last] => (*first, *last),
+    };
+    let span = Duration::from_secs(last.time.checked_sub(first.time)?);
+    let change = f64::from(last.battery) - f64::from(first.battery);
+    if span < MIN_SPAN || change == 0.0 || (change > 0.0) != charging {
+        return None;
+    }
+    let rate = change / span.as_secs_f64() * 3600.0;
+
+    let level = f64::from(state.battery_level);
+    let hours = |percent: f64| Duration::try_from_secs_f64(percent / rate.abs() * 3600.0).ok();
+    Some(Estimate {
+        rate,
+        time_remaining: if charging { None } else { hours(level) },
+        time_to_full: if charging { hours(100.0 - level) } else { None },
+    })
+}
+
+/// `2h 05m`, or `45m` under an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match minutes / 60 {
+        0 => format!("{minutes}m"),
+        hours => format!("{hours}h {:02}m", minutes % 60),
+    }
+}
+
+#[test]
+fn test_estimate() {
+    let sample = |minutes: u64, battery: u8, charging: bool| Sample {
+        time: 1_700_000_000 + minutes * 60,
+        connected: true,
+        battery,
+        charging,
+    };
+    let mut samples = vec![
+        sample(0, 40, true),
+        sample(30, 90, false),
+        sample(35, 80, false),
+        sample(95, 70, false),
+        sample(155, 60, false),
+    ];
+    let mut state = DeviceState {
+        headset_connected: Some(true),
+        battery_level: 60,
+        charging: Some(false),
+        ..DeviceState::default()
+    };
+    // 20% over the two hours between the first and last level change.
+    let now = 1_700_000_000 + 160 * 60;
+    let discharge = estimate(&samples, &state, now).unwrap();
+    assert!((discharge.rate + 10.0).abs() < 1e-9);
+    assert_eq!(discharge.time_remaining, Some(Duration::from_secs(6 * 3600)));
+    assert_eq!(discharge.time_to_full, None);
+    assert_eq!(format_duration(discharge.time_remaining.unwrap()), "6h 00m");
+
+    // Levels from before the window don't count.
+    assert_eq!(estimate(&samples, &state, now + 24 * 3600), None);
+
+    // Charging starts a new stretch with nothing to go on yet.
+    samples.push(sample(160, 60, true));
+    state.charging = Some(true);
+    assert_eq!(estimate(&samples, &state, now), None);
+
+    let line = samples[0].to_csv();
+    assert_eq!(Sample::from_csv(&line), Some(samples[0]));
+    assert_eq!(Sample::from_csv(HEADER), None);
+}
+
+#[test]
+fn test_history_file() {
+    let path = std::env::temp_dir().join(format!("hyperx-history-test-{}.csv", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut history = History::open(path.clone()).unwrap();
+    let mut state = DeviceState {
+        headset_connected: Some(true),
+        charging: Some(false),
+        ..DeviceState::default()
+    };
+    for level in (0..MAX_ROWS).map(|row| (row % 100 + 1) as u8) {
+        state.battery_level = level;
+        history.record(&state).unwrap();
+        history.record(&state).unwrap();
+    }
+    let samples = load(&path).unwrap();
+    assert!(samples.len() <= MAX_ROWS);
+    assert_eq!(samples.last().map(|sample| sample.battery), Some(100));
+
+    // Reopening cuts the history off with a disconnect.
+    History::open(path.clone()).unwrap();
+    assert!(!load(&path).unwrap().last().unwrap().connected);
+    let _ = std::fs::remove_file(&path);
+}
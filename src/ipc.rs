@@ -0,0 +1,252 @@
+//! Protocol spoken between `hyperxd` and its clients.
+//!
+//! Messages are JSON objects, one per line, over a Unix domain socket. Every
+//! connection opens with a `hello` carrying [`PROTOCOL_VERSION`]; the daemon
+//! answers with an error and hangs up if the versions differ.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub use crate::headset::Headset;
+use crate::{DeviceError, DeviceEvent, DeviceInfo, DeviceState, Setting};
+
+/// Bumped whenever a message changes shape.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Overrides the socket path when set.
+pub const SOCKET_ENV: &str = "HYPERXD_SOCKET";
+
+/// Where `hyperxd` listens: [`SOCKET_ENV`], else `$XDG_RUNTIME_DIR/hyperxd.sock`,
+/// else a per-user socket in the temp directory. The daemon makes the socket
+/// private to its user and both ends refuse one owned by another user.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return path.into();
+    }
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("hyperxd.sock");
+    }
+    let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+    std::env::temp_dir().join(format!("hyperxd-{user}.sock"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Hello { version: u32 },
+    /// The daemon's current view of the headset, without touching it.
+    State,
+    /// Re-queries the headset, then answers like `State`.
+    Refresh,
+    Info,
+    Set { setting: Setting },
+    /// Acknowledged with `Done`, then turns the connection into a stream of `Event` responses.
+    Subscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    /// `path` is the HID path of the headset the daemon has open, if any.
+    Hello { version: u32, path: Option<String> },
+    State { state: DeviceState },
+    Info { info: DeviceInfo },
+    Done,
+    Event { event: DeviceEvent, state: DeviceState },
+    Error { message: String },
+}
+
+impl From<io::Error> for DeviceError {
+    fn from(error: io::Error) -> Self {
+        DeviceError::Daemon(error.to_string())
+    }
+}
+
+pub(crate) fn write_message<T: Serialize>(stream: &mut impl Write, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// Reads one message, or `None` once the peer has hung up.
+pub(crate) fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+/// Fails if `path` exists and belongs to another user, who could be posing as
+/// the daemon in a shared directory like `/tmp`.
+pub(crate) fn check_owner(path: &Path) -> io::Result<()> {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    if metadata.uid() != unsafe { libc::geteuid() } {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} belongs to another user", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+type Handshake = (BufReader<UnixStream>, UnixStream, Option<String>);
+
+fn handshake(path: &Path) -> Result<Handshake, DeviceError> {
+    check_owner(path)?;
+    let mut writer = UnixStream::connect(path)?;
+    let mut reader = BufReader::new(writer.try_clone()?);
+    write_message(
+        &mut writer,
+        &Request::Hello {
+            version: PROTOCOL_VERSION,
+        },
+    )?;
+    match read_message(&mut reader)? {
+        Some(Response::Hello { path, .. }) => Ok((reader, writer, path)),
+        Some(Response::Error { message }) => Err(DeviceError::Daemon(message)),
+        _ => Err(DeviceError::Daemon("unexpected handshake reply".to_string())),
+    }
+}
+
+type EventResult = Result<(DeviceEvent, DeviceState), DeviceError>;
+
+/// Connection to a running `hyperxd`, mirroring the [`Device`] API.
+pub struct Client {
+    path: PathBuf,
+    device_path: Option<String>,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    state: DeviceState,
+    events: Option<mpsc::Receiver<EventResult>>,
+}
+
+impl Client {
+    /// Connects to the daemon at [`socket_path`].
+    pub fn connect() -> Result<Self, DeviceError> {
+        Self::connect_to(&socket_path())
+    }
+
+    pub fn connect_to(path: &Path) -> Result<Self, DeviceError> {
+        let (reader, writer, device_path) = handshake(path)?;
+        let mut client = Client {
+            path: path.to_path_buf(),
+            device_path,
+            reader,
+            writer,
+            state: DeviceState::default(),
+            events: None,
+        };
+        if let Response::State { state } = client.request(&Request::State)? {
+            client.state = state;
+        }
+        Ok(client)
+    }
+
+    fn request(&mut self, request: &Request) -> Result<Response, DeviceError> {
+        write_message(&mut self.writer, request)?;
+        match read_message(&mut self.reader)? {
+            Some(Response::Error { message }) => Err(DeviceError::Daemon(message)),
+            Some(response) => Ok(response),
+            None => Err(DeviceError::Daemon("connection closed".to_string())),
+        }
+    }
+
+    /// HID path of the headset the daemon had open when the client connected.
+    pub fn device_path(&self) -> Option<&str> {
+        self.device_path.as_deref()
+    }
+
+    /// Last state received from the daemon.
+    pub fn state(&self) -> &DeviceState {
+        &self.state
+    }
+
+    /// Has the daemon re-query the headset and updates [`Client::state`].
+    pub fn refresh_state(&mut self) -> Result<(), DeviceError> {
+        if let Response::State { state } = self.request(&Request::Refresh)? {
+            self.state = state;
+        }
+        Ok(())
+    }
+
+    pub fn device_info(&mut self) -> Result<DeviceInfo, DeviceError> {
+        match self.request(&Request::Info)? {
+            Response::Info { info } => Ok(info),
+            _ => Err(DeviceError::Daemon("unexpected reply".to_string())),
+        }
+    }
+
+    pub fn apply(&mut self, setting: Setting) -> Result<(), DeviceError> {
+        self.request(&Request::Set { setting }).map(|_| ())
+    }
+
+    /// Like [`Device::wait_for_updates`], fed by a subscription opened on first use.
+    pub fn wait_for_updates(&mut self, duration: Duration) -> Result<DeviceEvent, DeviceError> {
+        let events = match &self.events {
+            Some(events) => events,
+            None => self.events.insert(subscribe(&self.path)?),
+        };
+        match events.recv_timeout(duration) {
+            Ok(Ok((event, state))) => {
+                self.state = state;
+                Ok(event)
+            }
+            Ok(Err(error)) => {
+                self.events = None;
+                Err(error)
+            }
+            Err(RecvTimeoutError::Timeout) => Err(DeviceError::NoResponse()),
+            Err(RecvTimeoutError::Disconnected) => {
+                self.events = None;
+                Err(DeviceError::Daemon("connection closed".to_string()))
+            }
+        }
+    }
+}
+
+fn subscribe(path: &Path) -> Result<mpsc::Receiver<EventResult>, DeviceError> {
+    let (mut reader, mut writer, _) = handshake(path)?;
+    write_message(&mut writer, &Request::Subscribe)?;
+    // Nothing published after this acknowledgement can be missed.
+    match read_message(&mut reader)? {
+        Some(Response::Done) => {}
+        Some(Response::Error { message }) => return Err(DeviceError::Daemon(message)),
+        _ => return Err(DeviceError::Daemon("unexpected reply".to_string())),
+    }
+
+    let (sender, events) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        let event = match read_message(&mut reader) {
+            Ok(Some(Response::Event { event, state })) => Ok((event, state)),
+            Ok(Some(_)) => continue,
+            Ok(None) => Err(DeviceError::Daemon("connection closed".to_string())),
+            Err(error) => Err(error.into()),
+        };
+        let failed = event.is_err();
+        if sender.send(event).is_err() || failed {
+            break;
+        }
+    });
+    Ok(events)
+}
+
+#[test]
+fn test_message_format() {
+    let request = Request::Set {
+        setting: Setting::MonitorVolume(-2),
+    };
+    let line = serde_json::to_string(&request).unwrap();
+    assert_eq!(line, r#"{"type":"set","setting":{"monitor_volume":-2}}"#);
+    assert_eq!(serde_json::from_str::<Request>(&line).unwrap(), request);
+}
@@ -1,24 +1,152 @@
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use hidapi::{HidApi, HidDevice, HidError};
+use hidapi::{HidApi, HidError};
 use thistermination::TerminationFull;
 
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(target_os = "linux")]
+pub mod battery_tray;
+pub mod config;
+#[cfg(unix)]
+pub mod daemon;
+#[cfg(target_os = "linux")]
+pub mod dbus_service;
+pub mod emulator;
+pub mod headset;
+pub mod history;
+#[cfg(unix)]
+pub mod ipc;
+pub mod models;
+pub mod notifications;
+pub mod reconnect;
+pub mod report;
+pub mod status_bar;
+pub mod template;
+pub mod transport;
+pub mod tray_icon;
+
+pub use emulator::Emulator;
+pub use models::{Capabilities, Capability, Model, MODELS};
+pub use transport::{MockTransport, Transport};
+
+use models::{CLOUD_II_CORE_WIRELESS, MAX_REPORT_LEN};
+
+// First byte of every Cloud II Core Wireless report
+const MAGIC_BYTE: u8 = 102;
 
-// Possible vendor IDs [hyperx , HP]
-const VENDOR_IDS: [u16; 2] = [0x0951, 0x03F0];
-// Possible Cloud II Core Wireless product IDs
-const PRODUCT_IDS: [u16; 1] = [0x0995];
+// How long the query_* methods wait for the matching reply by default
+const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An attached dongle as reported by hidapi.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceDescriptor {
+    pub model: &'static str,
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
 
-const MAGIC_BYTE: u8 = 102;
+impl std::fmt::Display for DeviceDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {:04x}:{:04x} serial: {} ({})",
+            self.model,
+            self.path,
+            self.vendor_id,
+            self.product_id,
+            self.serial_number.as_deref().unwrap_or("N/A"),
+            self.product.as_deref().unwrap_or("Unknown product")
+        )
+    }
+}
+
+/// Picks one headset out of [`Device::enumerate`].
+///
+/// Parses from `path:<hid path>` or anything starting with `/`, `serial:<serial>`,
+/// or a plain index; any other string is taken as a serial number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Path(String),
+    Serial(String),
+    Index(usize),
+}
+
+impl DeviceSelector {
+    pub fn select<'a>(&self, descriptors: &'a [DeviceDescriptor]) -> Option<&'a DeviceDescriptor> {
+        match self {
+            DeviceSelector::Path(path) => descriptors.iter().find(|d| &d.path == path),
+            DeviceSelector::Serial(serial) => descriptors
+                .iter()
+                .find(|d| d.serial_number.as_ref() == Some(serial)),
+            DeviceSelector::Index(index) => descriptors.get(*index),
+        }
+    }
+}
 
+impl FromStr for DeviceSelector {
+    type Err = String;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        if selector.is_empty() {
+            return Err("empty device selector".to_string());
+        }
+        if let Some(path) = selector.strip_prefix("path:") {
+            Ok(DeviceSelector::Path(path.to_string()))
+        } else if let Some(serial) = selector.strip_prefix("serial:") {
+            Ok(DeviceSelector::Serial(serial.to_string()))
+        } else if selector.starts_with('/') {
+            Ok(DeviceSelector::Path(selector.to_string()))
+        } else if let Ok(index) = selector.parse() {
+            Ok(DeviceSelector::Index(index))
+        } else {
+            Ok(DeviceSelector::Serial(selector.to_string()))
+        }
+    }
+}
+
+impl Serialize for DeviceSelector {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DeviceSelector {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelector::Path(path) => write!(f, "path:{path}"),
+            DeviceSelector::Serial(serial) => write!(f, "serial:{serial}"),
+            DeviceSelector::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// Commands and reports, numbered as on the Cloud II Core Wireless.
+/// Other models map them to their own codes, see [`models`].
 #[derive(Debug, Eq, Copy, Clone, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 enum ReportByte {
     SetMonitorState = 1,
     SetPowerAutoOffTiming = 2,
     SetMicMuteState = 3,
-    //SetPlaybackMuteState = 4,
+    SetPlaybackMuteState = 4,
     SetMonitorVolume = 5,
     UpdateMicConnectionStatus = 7,
     UpdateMicMonitorStatus = 9,
@@ -26,18 +154,18 @@ enum ReportByte {
     UpdateConnectedStatus = 11,
     UpdateChargingStatus = 12,
     UpdateBatteryStatus = 13,
-    //SetNoiseGateState = 15,
-    //GetDeviceInformation = 129,
+    SetNoiseGateState = 15,
+    GetDeviceInformation = 129,
     GetConnectedStatus = 130,
     GetMonitorState = 132,
     GetPowerAutoOffTiming = 133,
     GetMicMuteState = 134,
-    //GetPlaybackMuteState = 135,
+    GetPlaybackMuteState = 135,
     GetMonitorVolume = 136,
     GetBatteryLevel = 137,
     GetChargerState = 138,
     GetMicPlugState = 140,
-    //GetNoiseGateState = 141,
+    GetNoiseGateState = 141,
 }
 
 impl From<TryFromPrimitiveError<ReportByte>> for DeviceError {
@@ -46,7 +174,48 @@ impl From<TryFromPrimitiveError<ReportByte>> for DeviceError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Identity of a connected unit: the `GetDeviceInformation` reply merged with the USB strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+    pub serial_number: Option<String>,
+    pub firmware_version: Option<FirmwareVersion>,
+    pub hardware_revision: Option<u8>,
+}
+
+impl std::fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn or_unknown<T: ToString>(value: &Option<T>) -> String {
+            value
+                .as_ref()
+                .map_or("Unknown".to_string(), T::to_string)
+        }
+        write!(
+            f,
+            "Product: {}\nManufacturer: {}\nSerial Number: {}\nFirmware Version: {}\nHardware Revision: {}",
+            or_unknown(&self.product),
+            or_unknown(&self.manufacturer),
+            or_unknown(&self.serial_number),
+            or_unknown(&self.firmware_version),
+            or_unknown(&self.hardware_revision)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DeviceEvent {
     MicConnected(bool),
     MonitoringMic(bool),
@@ -59,49 +228,53 @@ pub enum DeviceEvent {
     SetBatteryLevel(u8),
     MonitorVolume(u8),
     SetMonitorVolume(u8),
+    NoiseGate(bool),
+    PlaybackMuted(bool),
+    DeviceInformation(FirmwareVersion, u8),
 }
 
 impl DeviceEvent {
+    /// Decodes a Cloud II Core Wireless report.
     pub fn get_event_from_buf(buf: &[u8; 8], len: usize) -> Result<Self, DeviceError> {
-        if len == 0 {
-            return Err(DeviceError::NoResponse());
-        }
-        if len != 8 {
-            return Err(DeviceError::UnknownResponse(buf.clone(), len));
-        }
-
-        let original_buf = buf;
-
-        let (&byte, buf) = buf.split_first().unwrap();
-        if byte != MAGIC_BYTE {
-            return Err(DeviceError::UnknownResponse(original_buf.clone(), len));
-        }
-
-        let (&byte, buf) = buf.split_first().unwrap();
-        let command = ReportByte::try_from(byte).map_err(|err| {
-            dbg!(&original_buf);
-            err
-        })?;
+        CLOUD_II_CORE_WIRELESS
+            .protocol
+            .decode(&buf[..len.min(buf.len())])
+            .map(|(_, event)| event)
+    }
 
+    /// Builds the event for a report of `command`, `buf` holding the bytes after the command code.
+    fn from_payload(command: ReportByte, buf: &[u8; 8], battery_offset: usize) -> Self {
         match command {
-            ReportByte::SetMonitorState => Ok(Self::MonitoringMic(buf[0] == 1)),
-            ReportByte::SetPowerAutoOffTiming => Ok(Self::SetTimeout(buf[0])),
-            ReportByte::SetMicMuteState => Ok(Self::MicMuted(buf[0] == 1)),
-            ReportByte::SetMonitorVolume => Ok(Self::SetMonitorVolume(buf[0])),
-            ReportByte::UpdateMicConnectionStatus => Ok(Self::MicConnected(buf[0] == 1)),
-            ReportByte::UpdateMicMonitorStatus => Ok(Self::MonitoringMic(buf[0] == 1)),
-            ReportByte::UpdateMicMuteStatus => Ok(Self::MicMuted(buf[0] == 1)),
-            ReportByte::UpdateConnectedStatus => Ok(Self::HeadsetConnected(buf[0] == 1)),
-            ReportByte::UpdateChargingStatus => Ok(Self::Charging(buf[0] == 1)),
-            ReportByte::UpdateBatteryStatus => Ok(Self::ChargeLevel(buf[2])),
-            ReportByte::GetMonitorState => Ok(Self::MonitoringMic(buf[0] == 1)),
-            ReportByte::GetPowerAutoOffTiming => Ok(Self::GetTimeout(buf[0])),
-            ReportByte::GetMicMuteState => Ok(Self::MicMuted(buf[0] == 1)),
-            ReportByte::GetMonitorVolume => Ok(Self::MonitorVolume(buf[0])),
-            ReportByte::GetBatteryLevel => Ok(Self::SetBatteryLevel(buf[2])),
-            ReportByte::GetChargerState => Ok(Self::Charging(buf[0] == 1)),
-            ReportByte::GetMicPlugState => Ok(Self::MicConnected(buf[0] == 1)),
-            ReportByte::GetConnectedStatus => Ok(Self::HeadsetConnected(buf[0] == 1)),
+            ReportByte::SetMonitorState => Self::MonitoringMic(buf[0] == 1),
+            ReportByte::SetPowerAutoOffTiming => Self::SetTimeout(buf[0]),
+            ReportByte::SetMicMuteState => Self::MicMuted(buf[0] == 1),
+            ReportByte::SetPlaybackMuteState => Self::PlaybackMuted(buf[0] == 1),
+            ReportByte::SetMonitorVolume => Self::SetMonitorVolume(buf[0]),
+            ReportByte::SetNoiseGateState => Self::NoiseGate(buf[0] == 1),
+            ReportByte::UpdateMicConnectionStatus => Self::MicConnected(buf[0] == 1),
+            ReportByte::UpdateMicMonitorStatus => Self::MonitoringMic(buf[0] == 1),
+            ReportByte::UpdateMicMuteStatus => Self::MicMuted(buf[0] == 1),
+            ReportByte::UpdateConnectedStatus => Self::HeadsetConnected(buf[0] == 1),
+            ReportByte::UpdateChargingStatus => Self::Charging(buf[0] == 1),
+            ReportByte::UpdateBatteryStatus => Self::ChargeLevel(buf[battery_offset]),
+            ReportByte::GetMonitorState => Self::MonitoringMic(buf[0] == 1),
+            ReportByte::GetPowerAutoOffTiming => Self::GetTimeout(buf[0]),
+            ReportByte::GetMicMuteState => Self::MicMuted(buf[0] == 1),
+            ReportByte::GetPlaybackMuteState => Self::PlaybackMuted(buf[0] == 1),
+            ReportByte::GetMonitorVolume => Self::MonitorVolume(buf[0]),
+            ReportByte::GetBatteryLevel => Self::SetBatteryLevel(buf[battery_offset]),
+            ReportByte::GetChargerState => Self::Charging(buf[0] == 1),
+            ReportByte::GetMicPlugState => Self::MicConnected(buf[0] == 1),
+            // Payload: firmware major, firmware minor, hardware revision
+            ReportByte::GetDeviceInformation => Self::DeviceInformation(
+                FirmwareVersion {
+                    major: buf[0],
+                    minor: buf[1],
+                },
+                buf[2],
+            ),
+            ReportByte::GetConnectedStatus => Self::HeadsetConnected(buf[0] == 1),
+            ReportByte::GetNoiseGateState => Self::NoiseGate(buf[0] == 1),
         }
     }
 }
@@ -115,14 +288,84 @@ pub enum DeviceError {
     #[termination(msg("No response."))]
     NoResponse(),
     #[termination(msg("Unknown response: {0:?} with length: {1:?}"))]
-    UnknownResponse([u8; 8], usize),
+    UnknownResponse(Vec<u8>, usize),
     #[termination(msg("Unknown command: {0}"))]
     UnknownCommand(u8),
+    #[termination(msg("Timed out waiting for the reply to command {0}."))]
+    Timeout(u8),
+    #[termination(msg("{0} does not support {1}."))]
+    Unsupported(&'static str, Capability),
+    #[termination(msg("Device worker stopped."))]
+    WorkerStopped(),
+    #[termination(msg("Invalid emulator script: {0}"))]
+    InvalidScript(String),
+    #[termination(msg("Daemon: {0}"))]
+    Daemon(String),
+    #[termination(msg("Invalid config: {0}"))]
+    InvalidConfig(String),
+    #[termination(msg("No profile named {0}."))]
+    NoSuchProfile(String),
+    #[termination(msg("Invalid template: {0}"))]
+    InvalidTemplate(String),
+    #[termination(msg("The headset is off or out of range."))]
+    HeadsetDisconnected(),
 }
 
-#[derive(Debug)]
-pub struct Device {
-    hid_device: HidDevice,
+/// A setting that can be written to the headset, see [`Device::apply`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Setting {
+    MicMuted(bool),
+    PlaybackMuted(bool),
+    MicMonitored(bool),
+    MonitorVolume(i8),
+    Timeout(u8),
+    NoiseGate(bool),
+}
+
+impl Setting {
+    /// What the headset needs to support for this setting to be written.
+    pub fn capability(&self) -> Capability {
+        match self {
+            Setting::MicMuted(_) => Capability::MicMute,
+            Setting::PlaybackMuted(_) => Capability::PlaybackMute,
+            Setting::MicMonitored(_) => Capability::Sidetone,
+            Setting::MonitorVolume(_) => Capability::SidetoneVolume,
+            Setting::Timeout(_) => Capability::AutoOff,
+            Setting::NoiseGate(_) => Capability::NoiseGate,
+        }
+    }
+
+    /// Whether `state` already holds this value.
+    pub fn is_applied(&self, state: &DeviceState) -> bool {
+        match *self {
+            Setting::MicMuted(muted) => state.muted == Some(muted),
+            Setting::PlaybackMuted(muted) => state.playback_muted == Some(muted),
+            Setting::MicMonitored(monitored) => state.mic_monitored == Some(monitored),
+            Setting::MonitorVolume(volume) => state.monitor_volume() == volume,
+            Setting::Timeout(minutes) => state.timeout == minutes,
+            Setting::NoiseGate(enabled) => state.noise_gate == Some(enabled),
+        }
+    }
+
+    /// Updates `state` as if the headset had reported this value.
+    pub fn apply_to(&self, state: &mut DeviceState) {
+        match *self {
+            Setting::MicMuted(muted) => state.muted = Some(muted),
+            Setting::PlaybackMuted(muted) => state.playback_muted = Some(muted),
+            Setting::MicMonitored(monitored) => state.mic_monitored = Some(monitored),
+            Setting::MonitorVolume(volume) => state.monitor_volume = volume as u8,
+            Setting::Timeout(minutes) => state.timeout = minutes,
+            Setting::NoiseGate(enabled) => state.noise_gate = Some(enabled),
+        }
+    }
+}
+
+/// Snapshot of everything known about a headset, as shared with daemon clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub model: String,
+    pub capabilities: Capabilities,
     pub headset_connected: Option<bool>,
     pub battery_level: u8,
     pub charging: Option<bool>,
@@ -131,35 +374,137 @@ pub struct Device {
     pub mic_monitored: Option<bool>,
     pub timeout: u8,
     pub monitor_volume: u8,
+    pub noise_gate: Option<bool>,
+    pub playback_muted: Option<bool>,
+    pub firmware_version: Option<FirmwareVersion>,
+    pub hardware_revision: Option<u8>,
 }
 
-impl std::fmt::Display for Device {
+impl DeviceState {
+    /// The monitor volume, from -5 to 5. The headset reports it as a raw byte.
+    pub fn monitor_volume(&self) -> i8 {
+        self.monitor_volume as i8
+    }
+
+    /// `value` if the headset is on and has `capability`. Readings outlive the
+    /// headset going off, and plain numbers read 0 until it answers.
+    pub fn known<T>(&self, capability: Capability, value: T) -> Option<T> {
+        (self.headset_connected == Some(true) && self.capabilities.contains(capability)).then_some(value)
+    }
+}
+
+impl std::fmt::Display for DeviceState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Headset Connected {:?}\nBattery: {}\nCharging: {:?}\nMic Connected: {:?}\nMic Muted: {:?}\nMonitor On: {:?}\nMonitor Volume:{}\nIdle Timeout: {}\nMonitor Volume: {}",
-            self.headset_connected, self.battery_level, self.charging, self.mic_connected, self.muted, self.mic_monitored, self.monitor_volume, self.timeout, self.monitor_volume
+            "Headset Connected: {:?}\nBattery: {}\nCharging: {:?}\nMic Connected: {:?}\nMic Muted: {:?}\nMonitor On: {:?}\nMonitor Volume: {}\nIdle Timeout: {}\nNoise Gate: {:?}\nPlayback Muted: {:?}",
+            self.headset_connected, self.battery_level, self.charging, self.mic_connected, self.muted, self.mic_monitored, self.monitor_volume(), self.timeout, self.noise_gate, self.playback_muted
         )
     }
 }
+
+#[derive(Debug)]
+pub struct Device {
+    transport: Box<dyn Transport>,
+    model: &'static Model,
+    pending_events: VecDeque<DeviceEvent>,
+    response_timeout: Duration,
+    path: Option<String>,
+    pub headset_connected: Option<bool>,
+    pub battery_level: u8,
+    pub charging: Option<bool>,
+    pub mic_connected: Option<bool>,
+    pub muted: Option<bool>,
+    pub mic_monitored: Option<bool>,
+    pub timeout: u8,
+    pub monitor_volume: u8,
+    pub noise_gate: Option<bool>,
+    pub playback_muted: Option<bool>,
+    pub firmware_version: Option<FirmwareVersion>,
+    pub hardware_revision: Option<u8>,
+}
+
+impl std::fmt::Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.state().fmt(f)
+    }
+}
 impl Device {
+    /// Opens the first attached headset.
     pub fn new() -> Result<Self, DeviceError> {
+        Self::open(&DeviceSelector::Index(0))
+    }
+
+    /// Lists attached headsets in the order [`DeviceSelector::Index`] refers to them.
+    pub fn enumerate() -> Result<Vec<DeviceDescriptor>, DeviceError> {
+        if std::env::var(emulator::EMULATOR_ENV).is_ok() {
+            return Ok(vec![DeviceDescriptor {
+                model: CLOUD_II_CORE_WIRELESS.name,
+                path: emulator::EMULATOR_PATH.to_string(),
+                serial_number: Some(emulator::HeadsetState::default().serial_number),
+                product: Some("HyperX Cloud II Core Wireless (emulated)".to_string()),
+                vendor_id: CLOUD_II_CORE_WIRELESS.vendor_ids[0],
+                product_id: CLOUD_II_CORE_WIRELESS.product_ids[0],
+            }]);
+        }
+
         let hid_api = HidApi::new()?;
-        let hid_device = hid_api
-            .device_list()
-            .find_map(|info| {
-                if PRODUCT_IDS.contains(&info.product_id())
-                    && VENDOR_IDS.contains(&info.vendor_id())
-                {
-                    Some(hid_api.open(info.vendor_id(), info.product_id()))
-                } else {
-                    None
-                }
-            })
-            .ok_or(DeviceError::NoDeviceFound())??;
+        Ok(Self::descriptors(&hid_api))
+    }
 
+    fn descriptors(hid_api: &HidApi) -> Vec<DeviceDescriptor> {
+        let interfaces = hid_api.device_list().filter_map(|info| {
+            let model = Model::find(info.vendor_id(), info.product_id())?;
+            let descriptor = DeviceDescriptor {
+                model: model.name,
+                path: info.path().to_string_lossy().into_owned(),
+                serial_number: info.serial_number().filter(|s| !s.is_empty()).map(str::to_string),
+                product: info.product_string().map(str::to_string),
+                vendor_id: info.vendor_id(),
+                product_id: info.product_id(),
+            };
+            Some((descriptor, info.interface_number()))
+        });
+        first_interfaces(interfaces)
+    }
+
+    /// Opens the headset picked by `selector`.
+    pub fn open(selector: &DeviceSelector) -> Result<Self, DeviceError> {
+        if let Ok(script) = std::env::var(emulator::EMULATOR_ENV) {
+            let emulator = Emulator::from_env_value(&script).map_err(DeviceError::InvalidScript)?;
+            let mut device = Self::with_transport(emulator);
+            device.path = Some(emulator::EMULATOR_PATH.to_string());
+            return Ok(device);
+        }
+
+        let hid_api = HidApi::new()?;
+        let descriptors = Self::descriptors(&hid_api);
+        let descriptor = selector
+            .select(&descriptors)
+            .ok_or(DeviceError::NoDeviceFound())?;
+        let path = CString::new(descriptor.path.clone()).map_err(|_| DeviceError::NoDeviceFound())?;
+        let hid_device = hid_api.open_path(&path)?;
+        let model = Model::find(descriptor.vendor_id, descriptor.product_id)
+            .ok_or(DeviceError::NoDeviceFound())?;
+
+        let mut device = Self::with_model(hid_device, model);
+        device.path = Some(descriptor.path.clone());
+        Ok(device)
+    }
+
+    /// Builds a Cloud II Core Wireless device on top of any [`Transport`], e.g. a [`MockTransport`] in tests.
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self::with_model(transport, &CLOUD_II_CORE_WIRELESS)
+    }
+
+    /// Builds a device that speaks the protocol of `model`.
+    pub fn with_model(transport: impl Transport + 'static, model: &'static Model) -> Self {
         let device = Device {
-            hid_device,
+            transport: Box::new(transport),
+            model,
+            pending_events: VecDeque::new(),
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            path: None,
             headset_connected: None,
             battery_level: 0,
             charging: None,
@@ -168,10 +513,14 @@ impl Device {
             mic_monitored: None,
             timeout: 0,
             monitor_volume: 0,
+            noise_gate: None,
+            playback_muted: None,
+            firmware_version: None,
+            hardware_revision: None,
         };
 
         device.sync_state();
-        Ok(device)
+        device
     }
 
     fn update_self_with_event(&mut self, event: &DeviceEvent) {
@@ -181,22 +530,79 @@ impl Device {
             DeviceEvent::MicMuted(muted) => self.muted = Some(*muted),
             DeviceEvent::HeadsetConnected(connected) => self.headset_connected = Some(*connected),
             DeviceEvent::Charging(charging) => self.charging = Some(*charging),
-            DeviceEvent::ChargeLevel(level) => self.battery_level = level.clone(),
-            DeviceEvent::GetTimeout(timeout) => self.timeout = timeout.clone(),
-            DeviceEvent::SetTimeout(timeout) => self.timeout = timeout.clone(),
+            DeviceEvent::ChargeLevel(level) => self.battery_level = *level,
+            DeviceEvent::GetTimeout(timeout) => self.timeout = *timeout,
+            DeviceEvent::SetTimeout(timeout) => self.timeout = *timeout,
             DeviceEvent::SetBatteryLevel(level) => self.battery_level = *level,
             DeviceEvent::MonitorVolume(volume) => self.monitor_volume = *volume,
             DeviceEvent::SetMonitorVolume(volume) => self.monitor_volume = *volume,
+            DeviceEvent::NoiseGate(enabled) => self.noise_gate = Some(*enabled),
+            DeviceEvent::PlaybackMuted(muted) => self.playback_muted = Some(*muted),
+            DeviceEvent::DeviceInformation(firmware, revision) => {
+                self.firmware_version = Some(*firmware);
+                self.hardware_revision = Some(*revision);
+            }
         };
+        // Models that can't tell are on as long as they answer.
+        if !self.model.capabilities.contains(Capability::Connection) {
+            self.headset_connected = Some(true);
+        }
+    }
+
+    pub fn model(&self) -> &'static Model {
+        self.model
+    }
+
+    /// HID path the device was opened at, `None` for a bare [`Transport`].
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Features of the connected headset's model, minus any of its
+    /// [`Model::firmware_requirements`] the firmware is known to miss. No
+    /// supported model has firmware-gated features yet.
+    pub fn capabilities(&self) -> Capabilities {
+        self.model.capabilities_for(self.firmware_version)
+    }
+
+    /// Copies the current state out of the device.
+    pub fn state(&self) -> DeviceState {
+        DeviceState {
+            model: self.model.name.to_string(),
+            capabilities: self.capabilities(),
+            headset_connected: self.headset_connected,
+            battery_level: self.battery_level,
+            charging: self.charging,
+            mic_connected: self.mic_connected,
+            muted: self.muted,
+            mic_monitored: self.mic_monitored,
+            timeout: self.timeout,
+            monitor_volume: self.monitor_volume,
+            noise_gate: self.noise_gate,
+            playback_muted: self.playback_muted,
+            firmware_version: self.firmware_version,
+            hardware_revision: self.hardware_revision,
+        }
+    }
+
+    /// How long the `query_*` methods wait for their reply before giving up.
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.response_timeout = timeout;
     }
 
     pub fn wait_for_updates(&mut self, duration: Duration) -> Result<DeviceEvent, DeviceError> {
-        let mut buf: [u8; 8] = [0u8; 8];
-        let res = self
-            .hid_device
-            .read_timeout(&mut buf[..], duration.as_millis() as i32)?;
-        match DeviceEvent::get_event_from_buf(&buf, res) {
-            Ok(event) => {
+        // Events that arrived while a query was waiting for its reply come first.
+        if let Some(event) = self.pending_events.pop_front() {
+            return Ok(event);
+        }
+
+        let mut buf = [0u8; MAX_REPORT_LEN];
+        let res = self.transport.read_timeout(
+            &mut buf[..self.model.protocol.response_len],
+            duration.as_millis() as i32,
+        )?;
+        match self.model.protocol.decode(&buf[..res]) {
+            Ok((_, event)) => {
                 self.update_self_with_event(&event);
                 Ok(event)
             }
@@ -204,84 +610,267 @@ impl Device {
         }
     }
 
-    pub fn mute_mic(&self, mute: bool) -> Result<usize, HidError> {
-        return self
-            .hid_device
-            .write(&[MAGIC_BYTE, ReportByte::SetMicMuteState as u8, mute as u8]);
+    /// Frames `command` for this model and writes it.
+    fn send(&self, command: ReportByte, args: &[u8]) -> Result<usize, DeviceError> {
+        if !self.capabilities().contains(command.capability()) {
+            return Err(DeviceError::Unsupported(self.model.name, command.capability()));
+        }
+        let report = self.model.encode(command, args)?;
+        Ok(self.transport.write(&report)?)
+    }
+
+    pub fn mute_mic(&self, mute: bool) -> Result<usize, DeviceError> {
+        self.send(ReportByte::SetMicMuteState, &[mute as u8])
     }
 
-    pub fn monitor_mic(&self, mute: bool) -> Result<usize, HidError> {
-        return self
-            .hid_device
-            .write(&[MAGIC_BYTE, ReportByte::SetMonitorState as u8, mute as u8]);
+    pub fn mute_playback(&self, mute: bool) -> Result<usize, DeviceError> {
+        self.send(ReportByte::SetPlaybackMuteState, &[mute as u8])
     }
 
-    pub fn set_timeout(&self, timeout: u8) -> Result<usize, HidError> {
-        return self.hid_device.write(&[
-            MAGIC_BYTE,
-            ReportByte::SetPowerAutoOffTiming as u8,
-            timeout,
-        ]);
+    pub fn monitor_mic(&self, mute: bool) -> Result<usize, DeviceError> {
+        self.send(ReportByte::SetMonitorState, &[mute as u8])
     }
 
-    pub fn set_monitor_volume(&self, volume: i8) -> Result<usize, HidError> {
+    pub fn set_timeout(&self, timeout: u8) -> Result<usize, DeviceError> {
+        self.send(ReportByte::SetPowerAutoOffTiming, &[timeout])
+    }
+
+    pub fn set_monitor_volume(&self, volume: i8) -> Result<usize, DeviceError> {
         //TODO must be -5 <= volume <= 5
-        return self.hid_device.write(&[
-            MAGIC_BYTE,
-            ReportByte::SetMonitorVolume as u8,
-            volume.to_ne_bytes()[0],
-        ]);
+        self.send(ReportByte::SetMonitorVolume, &volume.to_ne_bytes())
+    }
+
+    pub fn set_noise_gate(&self, enabled: bool) -> Result<usize, DeviceError> {
+        self.send(ReportByte::SetNoiseGateState, &[enabled as u8])
+    }
+
+    /// Writes `setting` with the matching setter.
+    pub fn apply(&self, setting: Setting) -> Result<usize, DeviceError> {
+        match setting {
+            Setting::MicMuted(muted) => self.mute_mic(muted),
+            Setting::PlaybackMuted(muted) => self.mute_playback(muted),
+            Setting::MicMonitored(monitored) => self.monitor_mic(monitored),
+            Setting::MonitorVolume(volume) => self.set_monitor_volume(volume),
+            Setting::Timeout(timeout) => self.set_timeout(timeout),
+            Setting::NoiseGate(enabled) => self.set_noise_gate(enabled),
+        }
+    }
+
+    pub fn update_battery_level(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetBatteryLevel, &[])
+    }
+
+    pub fn get_monitor_volume(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetMonitorVolume, &[])
+    }
+
+    pub fn get_timeout(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetPowerAutoOffTiming, &[])
+    }
+
+    pub fn get_monitor_state(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetMonitorState, &[])
+    }
+
+    pub fn get_mic_mute_state(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetMicMuteState, &[])
+    }
+
+    pub fn get_playback_mute_state(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetPlaybackMuteState, &[])
+    }
+
+    pub fn get_charger_state(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetChargerState, &[])
+    }
+
+    pub fn get_mic_connected(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetMicPlugState, &[])
+    }
+
+    pub fn get_headset_connected(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetConnectedStatus, &[])
+    }
+
+    pub fn get_noise_gate_state(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetNoiseGateState, &[])
+    }
+
+    pub fn get_device_information(&self) -> Result<usize, DeviceError> {
+        self.send(ReportByte::GetDeviceInformation, &[])
+    }
+
+    /// Takes in the reports already waiting, so older replies can't answer the next queries.
+    fn drain_reports(&mut self) -> Result<(), DeviceError> {
+        let mut buf = [0u8; MAX_REPORT_LEN];
+        loop {
+            let res = self
+                .transport
+                .read_timeout(&mut buf[..self.model.protocol.response_len], 0)?;
+            if res == 0 {
+                return Ok(());
+            }
+            if let Ok((_, event)) = self.model.protocol.decode(&buf[..res]) {
+                self.update_self_with_event(&event);
+                self.pending_events.push_back(event);
+            }
+        }
+    }
+
+    /// Sends `command` and blocks until its reply arrives, routing any other
+    /// report into state and queueing it for [`Device::wait_for_updates`].
+    fn query(&mut self, command: ReportByte) -> Result<DeviceEvent, DeviceError> {
+        self.send(command, &[])?;
+
+        let deadline = Instant::now() + self.response_timeout;
+        let mut buf = [0u8; MAX_REPORT_LEN];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(DeviceError::Timeout(command as u8));
+            }
+            let res = self.transport.read_timeout(
+                &mut buf[..self.model.protocol.response_len],
+                remaining.as_millis() as i32,
+            )?;
+            match self.model.protocol.decode(&buf[..res]) {
+                Ok((reply, event)) => {
+                    self.update_self_with_event(&event);
+                    if reply == command {
+                        return Ok(event);
+                    }
+                    self.pending_events.push_back(event);
+                }
+                Err(DeviceError::NoResponse())
+                | Err(DeviceError::UnknownResponse(_, _))
+                | Err(DeviceError::UnknownCommand(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn query_flag(&mut self, command: ReportByte) -> Result<bool, DeviceError> {
+        match self.query(command)? {
+            DeviceEvent::MicConnected(flag)
+            | DeviceEvent::MonitoringMic(flag)
+            | DeviceEvent::MicMuted(flag)
+            | DeviceEvent::HeadsetConnected(flag)
+            | DeviceEvent::Charging(flag)
+            | DeviceEvent::NoiseGate(flag)
+            | DeviceEvent::PlaybackMuted(flag) => Ok(flag),
+            event => unreachable!("{command:?} answered with {event:?}"),
+        }
+    }
+
+    fn query_value(&mut self, command: ReportByte) -> Result<u8, DeviceError> {
+        match self.query(command)? {
+            DeviceEvent::ChargeLevel(value)
+            | DeviceEvent::SetTimeout(value)
+            | DeviceEvent::GetTimeout(value)
+            | DeviceEvent::SetBatteryLevel(value)
+            | DeviceEvent::MonitorVolume(value)
+            | DeviceEvent::SetMonitorVolume(value) => Ok(value),
+            event => unreachable!("{command:?} answered with {event:?}"),
+        }
     }
 
-    pub fn update_battery_level(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetBatteryLevel as u8])
+    pub fn query_headset_connected(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetConnectedStatus)
     }
 
-    pub fn get_monitor_volume(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetMonitorVolume as u8])
+    pub fn query_battery_level(&mut self) -> Result<u8, DeviceError> {
+        self.query_value(ReportByte::GetBatteryLevel)
     }
 
-    pub fn get_timeout(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetPowerAutoOffTiming as u8])
+    pub fn query_charging(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetChargerState)
     }
 
-    pub fn get_monitor_state(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetMonitorState as u8])
+    pub fn query_mic_connected(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetMicPlugState)
     }
 
-    pub fn get_mic_mute_state(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetMicMuteState as u8])
+    pub fn query_mic_muted(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetMicMuteState)
     }
 
-    pub fn get_charger_state(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetChargerState as u8])
+    pub fn query_playback_muted(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetPlaybackMuteState)
     }
 
-    pub fn get_mic_connected(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetMicPlugState as u8])
+    pub fn query_mic_monitored(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetMonitorState)
     }
 
-    pub fn get_headset_connected(&self) -> Result<usize, HidError> {
-        self.hid_device
-            .write(&[MAGIC_BYTE, ReportByte::GetConnectedStatus as u8])
+    pub fn query_timeout(&mut self) -> Result<u8, DeviceError> {
+        self.query_value(ReportByte::GetPowerAutoOffTiming)
+    }
+
+    pub fn query_monitor_volume(&mut self) -> Result<u8, DeviceError> {
+        self.query_value(ReportByte::GetMonitorVolume)
+    }
+
+    pub fn query_noise_gate(&mut self) -> Result<bool, DeviceError> {
+        self.query_flag(ReportByte::GetNoiseGateState)
+    }
+
+    /// Asks the headset for its firmware and hardware revision and merges them with
+    /// the USB descriptor strings. Firmware fields stay `None` if the headset doesn't answer.
+    pub fn query_device_info(&mut self) -> Result<DeviceInfo, DeviceError> {
+        match self.query(ReportByte::GetDeviceInformation) {
+            Ok(_) | Err(DeviceError::Timeout(_)) => {}
+            Err(error) => return Err(error),
+        }
+        Ok(DeviceInfo {
+            product: self.transport.product()?,
+            manufacturer: self.transport.manufacturer()?,
+            serial_number: self.transport.serial_number()?,
+            firmware_version: self.firmware_version,
+            hardware_revision: self.hardware_revision,
+        })
+    }
+
+    /// Blocking counterpart of [`Device::sync_state`]: queries everything the model
+    /// supports and waits for each reply. Stops after the connection query when the
+    /// headset is off, since the dongle won't answer the rest.
+    pub fn refresh_state(&mut self) -> Result<(), DeviceError> {
+        fn supported<T>(result: Result<T, DeviceError>) -> Result<Option<T>, DeviceError> {
+            match result {
+                Ok(value) => Ok(Some(value)),
+                Err(DeviceError::Unsupported(_, _)) => Ok(None),
+                Err(error) => Err(error),
+            }
+        }
+
+        // Replies to `sync_state` or to earlier writes may still be queued.
+        self.drain_reports()?;
+        if supported(self.query_headset_connected())? == Some(false) {
+            return Ok(());
+        }
+        supported(self.query_battery_level())?;
+        supported(self.query_timeout())?;
+        supported(self.query_mic_muted())?;
+        supported(self.query_playback_muted())?;
+        supported(self.query_mic_monitored())?;
+        supported(self.query_monitor_volume())?;
+        supported(self.query_charging())?;
+        supported(self.query_mic_connected())?;
+        supported(self.query_noise_gate())?;
+        Ok(())
     }
 
     pub fn sync_state(&self) {
-        self.get_headset_connected();
-        self.update_battery_level();
-        self.get_timeout();
-        self.get_mic_mute_state();
-        self.get_monitor_state();
-        self.get_monitor_volume();
-        self.get_charger_state();
-        self.get_mic_connected();
+        let _ = self.get_headset_connected();
+        let _ = self.get_device_information();
+        let _ = self.update_battery_level();
+        let _ = self.get_timeout();
+        let _ = self.get_mic_mute_state();
+        let _ = self.get_playback_mute_state();
+        let _ = self.get_monitor_state();
+        let _ = self.get_monitor_volume();
+        let _ = self.get_charger_state();
+        let _ = self.get_mic_connected();
+        let _ = self.get_noise_gate_state();
     }
     pub fn clear_state(&mut self) {
         self.headset_connected = None;
@@ -292,5 +881,105 @@ impl Device {
         self.mic_monitored = None;
         self.timeout = 0;
         self.monitor_volume = 0;
+        self.noise_gate = None;
+        self.playback_muted = None;
     }
 }
+
+/// Drops the other HID interfaces of each dongle, keeping the first one like
+/// hid_open does. Dongles with a serial number are told apart by it; dongles
+/// without one by each listing the interface first seen for their vendor and
+/// product ids, `-1` when the platform doesn't report it.
+fn first_interfaces(interfaces: impl IntoIterator<Item = (DeviceDescriptor, i32)>) -> Vec<DeviceDescriptor> {
+    let mut descriptors: Vec<DeviceDescriptor> = Vec::new();
+    let mut first_interface: Vec<(u16, u16, i32)> = Vec::new();
+    for (descriptor, interface) in interfaces {
+        let ids = (descriptor.vendor_id, descriptor.product_id);
+        let duplicate = match &descriptor.serial_number {
+            Some(_) => descriptors.iter().any(|known| {
+                (known.vendor_id, known.product_id) == ids && known.serial_number == descriptor.serial_number
+            }),
+            None => match first_interface.iter().find(|known| (known.0, known.1) == ids) {
+                Some(known) => known.2 != interface,
+                None => {
+                    first_interface.push((ids.0, ids.1, interface));
+                    false
+                }
+            },
+        };
+        if !duplicate {
+            descriptors.push(descriptor);
+        }
+    }
+    descriptors
+}
+
+#[test]
+fn test_query_routes_unsolicited_events() {
+    let mock = MockTransport::new();
+    let mut device = Device::with_transport(mock.clone());
+    device.set_response_timeout(Duration::from_millis(50));
+
+    mock.push_report(&[MAGIC_BYTE, ReportByte::UpdateMicMuteStatus as u8, 1, 0, 0, 0, 0, 0]);
+    mock.push_report(&[MAGIC_BYTE, ReportByte::GetBatteryLevel as u8, 0, 0, 55, 0, 0, 0]);
+    assert!(matches!(device.query_battery_level(), Ok(55)));
+    assert_eq!(device.battery_level, 55);
+    assert_eq!(device.muted, Some(true));
+    assert!(matches!(
+        device.wait_for_updates(Duration::ZERO),
+        Ok(DeviceEvent::MicMuted(true))
+    ));
+
+    let expected = ReportByte::GetPowerAutoOffTiming as u8;
+    assert!(matches!(device.query_timeout(), Err(DeviceError::Timeout(command)) if command == expected));
+}
+
+#[test]
+fn test_device_selector() {
+    let descriptor = |path: &str, serial: Option<&str>| DeviceDescriptor {
+        model: CLOUD_II_CORE_WIRELESS.name,
+        path: path.to_string(),
+        serial_number: serial.map(str::to_string),
+        product: None,
+        vendor_id: CLOUD_II_CORE_WIRELESS.vendor_ids[0],
+        product_id: CLOUD_II_CORE_WIRELESS.product_ids[0],
+    };
+    let descriptors = [
+        descriptor("/dev/hidraw1", Some("AAA")),
+        descriptor("/dev/hidraw4", None),
+    ];
+
+    let select = |selector: &str| {
+        selector
+            .parse::<DeviceSelector>()
+            .unwrap()
+            .select(&descriptors)
+            .map(|d| d.path.clone())
+    };
+    assert_eq!(select("1"), Some("/dev/hidraw4".to_string()));
+    assert_eq!(select("/dev/hidraw1"), Some("/dev/hidraw1".to_string()));
+    assert_eq!(select("AAA"), Some("/dev/hidraw1".to_string()));
+    assert_eq!(select("serial:1"), None);
+    assert_eq!(select("2"), None);
+}
+
+#[test]
+fn test_first_interfaces() {
+    let descriptor = |path: &str, serial: Option<&str>| DeviceDescriptor {
+        model: CLOUD_II_CORE_WIRELESS.name,
+        path: path.to_string(),
+        serial_number: serial.map(str::to_string),
+        product: None,
+        vendor_id: CLOUD_II_CORE_WIRELESS.vendor_ids[0],
+        product_id: CLOUD_II_CORE_WIRELESS.product_ids[0],
+    };
+    // Two dongles with a serial number and two without, three interfaces each.
+    let interfaces = [(0, Some("AAA")), (3, None), (6, None), (9, Some("BBB"))]
+        .into_iter()
+        .flat_map(|(first, serial)| {
+            (0..3).map(move |interface| (descriptor(&format!("/dev/hidraw{}", first + interface), serial), interface))
+        });
+
+    let paths: Vec<String> = first_interfaces(interfaces).into_iter().map(|d| d.path).collect();
+    assert_eq!(paths, ["/dev/hidraw0", "/dev/hidraw3", "/dev/hidraw6", "/dev/hidraw9"]);
+}
@@ -1,62 +1,63 @@
-use std::time::Duration;
-
-use hyper_x_cloud_ii_core_wireless::{Device, DeviceError};
-
-
-fn pair_device() -> Device {
-    loop {
-        match Device::new() {
-            Ok(device) => break device,
-            Err(error) => {
-                eprintln!("{error}");
-            }
-        };
-        std::thread::sleep(std::time::Duration::from_secs(1));
-    }
+use clap::Parser;
+use hyper_x_cloud_ii_core_wireless::config::{self, Config};
+use hyper_x_cloud_ii_core_wireless::reconnect::{HeadsetEvent, ReconnectingHeadset};
+use hyper_x_cloud_ii_core_wireless::template::Template;
+use hyper_x_cloud_ii_core_wireless::{DeviceError, DeviceSelector};
+
+#[derive(Parser, Debug)]
+#[clap(about = "Monitor a HyperX Cloud II Core Wireless headset.")]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "DEVICE",
+        help = "Headset to use: an index, a serial number or a HID path. Defaults to the first one."
+    )]
+    device: Option<DeviceSelector>,
 }
 
-fn handle_error(error: DeviceError, device: &mut Device) {
-
-    match error {
-        DeviceError::HidError(hidapi::HidError::HidApiError { message }) => {
-            if message == "No such device" {
-                eprintln!("No device found.");
-                *device = pair_device();
-            } else if message.contains("device disconnected") {
-                eprintln!("Device disconnected.");
-                *device = pair_device();
-            } 
-            else {
-                eprintln!("HID ERROR: {message}");
-                std::process::exit(1);            }
-        }
-        DeviceError::NoDeviceFound() => {
-            eprintln!("{}", DeviceError::NoDeviceFound());
-            device.clear_state();
-                   }
-        error => {
-            eprintln!("{error}");
-        }
-    }
-}
 
 fn main() {
-    let mut device = pair_device();
+    let args = Args::parse();
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let selector = args.device.or(config.device).unwrap_or(DeviceSelector::Index(0));
+    let interval = |value| {
+        config::seconds(value).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        })
+    };
+    let retry = interval(config.reconnect_interval);
+    let poll_interval = interval(config.monitor.poll_interval);
+    let template = config.monitor.template.as_deref().map(Template::parse).transpose().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    let mut device = ReconnectingHeadset::new(selector, retry);
 
     // Run loop
     loop {
-        match device.wait_for_updates(Duration::from_secs(10)) {
-            Ok(_) => {
-                
-            }
+        match device.wait_for_updates(poll_interval) {
+            Ok(HeadsetEvent::Disconnected) => eprintln!("Device disconnected."),
+            Ok(_) => {}
             Err(DeviceError::NoResponse()) => (),
             Err(DeviceError::UnknownResponse(_, _)) => (),
             Err(error) => {
+                eprintln!("{error}");
                 continue;
             }
         }
+        if !device.is_connected() {
+            continue;
+        }
 
         print!("{esc}c", esc = 27 as char);
-        print!("{}", device);
+        match &template {
+            Some(template) => println!("{}", template.render(&device.state())),
+            None => print!("{}", device.state()),
+        }
     }
 }
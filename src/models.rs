@@ -0,0 +1,456 @@
+//! Registry of supported headsets and the wire protocol each one speaks.
+//!
+//! Commands are always named after the Cloud II Core Wireless ([`ReportByte`]);
+//! every model maps the ones it understands to its own codes and framing.
+//! Only the Core Wireless table has been checked against hardware, the others
+//! follow the layouts used by other open source drivers for those headsets.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DeviceError, DeviceEvent, FirmwareVersion, ReportByte, MAGIC_BYTE};
+
+// Possible vendor IDs [hyperx , HP]
+const VENDOR_IDS: [u16; 2] = [0x0951, 0x03F0];
+
+/// Largest report any model sends, used to size read buffers.
+pub(crate) const MAX_REPORT_LEN: usize = 64;
+
+/// A feature a headset may or may not have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Connection,
+    Battery,
+    Charging,
+    MicMute,
+    MicPlug,
+    Sidetone,
+    SidetoneVolume,
+    AutoOff,
+    NoiseGate,
+    PlaybackMute,
+    DeviceInformation,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 11] = [
+        Capability::Connection,
+        Capability::Battery,
+        Capability::Charging,
+        Capability::MicMute,
+        Capability::MicPlug,
+        Capability::Sidetone,
+        Capability::SidetoneVolume,
+        Capability::AutoOff,
+        Capability::NoiseGate,
+        Capability::PlaybackMute,
+        Capability::DeviceInformation,
+    ];
+
+    const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Capability::Connection => "connection status",
+            Capability::Battery => "battery level",
+            Capability::Charging => "charging status",
+            Capability::MicMute => "mic mute",
+            Capability::MicPlug => "mic plug detection",
+            Capability::Sidetone => "sidetone",
+            Capability::SidetoneVolume => "sidetone volume",
+            Capability::AutoOff => "auto-off",
+            Capability::NoiseGate => "noise gate",
+            Capability::PlaybackMute => "playback mute",
+            Capability::DeviceInformation => "device information",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Set of [`Capability`] values, serialized as a list of names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(into = "Vec<Capability>", from = "Vec<Capability>")]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const fn empty() -> Self {
+        Capabilities(0)
+    }
+
+    pub const fn all() -> Self {
+        let mut capabilities = Self::empty();
+        let mut i = 0;
+        while i < Capability::ALL.len() {
+            capabilities = capabilities.with(Capability::ALL[i]);
+            i += 1;
+        }
+        capabilities
+    }
+
+    pub const fn with(self, capability: Capability) -> Self {
+        Capabilities(self.0 | capability.bit())
+    }
+
+    pub const fn without(self, capability: Capability) -> Self {
+        Capabilities(self.0 & !capability.bit())
+    }
+
+    pub const fn contains(&self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        Capability::ALL
+            .into_iter()
+            .filter(|capability| self.contains(*capability))
+    }
+}
+
+impl From<Capabilities> for Vec<Capability> {
+    fn from(capabilities: Capabilities) -> Self {
+        capabilities.iter().collect()
+    }
+}
+
+impl From<Vec<Capability>> for Capabilities {
+    fn from(list: Vec<Capability>) -> Self {
+        list.into_iter()
+            .fold(Capabilities::empty(), Capabilities::with)
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> = self
+            .iter()
+            .map(|capability| capability.to_string())
+            .collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+impl ReportByte {
+    /// The feature a command belongs to.
+    pub(crate) fn capability(self) -> Capability {
+        match self {
+            ReportByte::UpdateConnectedStatus | ReportByte::GetConnectedStatus => {
+                Capability::Connection
+            }
+            ReportByte::UpdateBatteryStatus | ReportByte::GetBatteryLevel => Capability::Battery,
+            ReportByte::UpdateChargingStatus | ReportByte::GetChargerState => Capability::Charging,
+            ReportByte::SetMicMuteState
+            | ReportByte::UpdateMicMuteStatus
+            | ReportByte::GetMicMuteState => Capability::MicMute,
+            ReportByte::UpdateMicConnectionStatus | ReportByte::GetMicPlugState => {
+                Capability::MicPlug
+            }
+            ReportByte::SetMonitorState
+            | ReportByte::UpdateMicMonitorStatus
+            | ReportByte::GetMonitorState => Capability::Sidetone,
+            ReportByte::SetMonitorVolume | ReportByte::GetMonitorVolume => {
+                Capability::SidetoneVolume
+            }
+            ReportByte::SetPowerAutoOffTiming | ReportByte::GetPowerAutoOffTiming => {
+                Capability::AutoOff
+            }
+            ReportByte::SetNoiseGateState | ReportByte::GetNoiseGateState => Capability::NoiseGate,
+            ReportByte::SetPlaybackMuteState | ReportByte::GetPlaybackMuteState => {
+                Capability::PlaybackMute
+            }
+            ReportByte::GetDeviceInformation => Capability::DeviceInformation,
+        }
+    }
+}
+
+/// Framing and command codes of one headset family.
+#[derive(Debug, Clone)]
+pub(crate) struct Protocol {
+    /// Bytes every request starts with, before the command code
+    request_header: &'static [u8],
+    /// Requests are zero padded to this length, 0 sends them as is
+    request_len: usize,
+    /// Bytes every report from the headset starts with, before the command code
+    response_header: &'static [u8],
+    /// Length of the reports read from the headset
+    pub(crate) response_len: usize,
+    /// Payload index of the battery level in battery reports
+    battery_offset: usize,
+    /// Wire code of every supported command; the first match wins when decoding
+    codes: &'static [(ReportByte, u8)],
+}
+
+impl Protocol {
+    fn code(&self, command: ReportByte) -> Option<u8> {
+        self.codes
+            .iter()
+            .find(|(known, _)| *known == command)
+            .map(|(_, code)| *code)
+    }
+
+    fn command(&self, code: u8) -> Option<ReportByte> {
+        self.codes
+            .iter()
+            .find(|(_, known)| *known == code)
+            .map(|(command, _)| *command)
+    }
+
+    /// Frames `command` with its arguments, or `None` if this protocol lacks it.
+    pub(crate) fn encode(&self, command: ReportByte, args: &[u8]) -> Option<Vec<u8>> {
+        let mut report = self.request_header.to_vec();
+        report.push(self.code(command)?);
+        report.extend_from_slice(args);
+        if report.len() < self.request_len {
+            report.resize(self.request_len, 0);
+        }
+        Some(report)
+    }
+
+    /// Decodes a report and also returns the command it answers.
+    pub(crate) fn decode(&self, buf: &[u8]) -> Result<(ReportByte, DeviceEvent), DeviceError> {
+        if buf.is_empty() {
+            return Err(DeviceError::NoResponse());
+        }
+        if buf.len() != self.response_len || !buf.starts_with(self.response_header) {
+            return Err(DeviceError::UnknownResponse(buf.to_vec(), buf.len()));
+        }
+
+        let code = buf[self.response_header.len()];
+        let command = self.command(code).ok_or(DeviceError::UnknownCommand(code))?;
+
+        let mut payload = [0u8; 8];
+        let rest = &buf[self.response_header.len() + 1..];
+        let len = rest.len().min(payload.len());
+        payload[..len].copy_from_slice(&rest[..len]);
+        Ok((
+            command,
+            DeviceEvent::from_payload(command, &payload, self.battery_offset),
+        ))
+    }
+}
+
+/// A supported headset family.
+#[derive(Debug, Clone)]
+pub struct Model {
+    pub name: &'static str,
+    pub vendor_ids: &'static [u16],
+    pub product_ids: &'static [u16],
+    pub capabilities: Capabilities,
+    /// Capabilities that need at least this firmware; older units lack them
+    pub firmware_requirements: &'static [(Capability, FirmwareVersion)],
+    pub(crate) protocol: Protocol,
+}
+
+impl Model {
+    pub fn find(vendor_id: u16, product_id: u16) -> Option<&'static Model> {
+        MODELS.iter().copied().find(|model| {
+            model.vendor_ids.contains(&vendor_id) && model.product_ids.contains(&product_id)
+        })
+    }
+
+    /// What a unit of this model can do. Firmware-gated capabilities are only
+    /// dropped once the firmware is known to be too old.
+    pub fn capabilities_for(&self, firmware: Option<FirmwareVersion>) -> Capabilities {
+        let Some(firmware) = firmware else {
+            return self.capabilities;
+        };
+        self.firmware_requirements
+            .iter()
+            .filter(|(_, required)| firmware < *required)
+            .fold(self.capabilities, |capabilities, (capability, _)| {
+                capabilities.without(*capability)
+            })
+    }
+
+    /// Frames `command` for this model, rejecting commands it doesn't have.
+    pub(crate) fn encode(&self, command: ReportByte, args: &[u8]) -> Result<Vec<u8>, DeviceError> {
+        let unsupported = || DeviceError::Unsupported(self.name, command.capability());
+        if !self.capabilities.contains(command.capability()) {
+            return Err(unsupported());
+        }
+        self.protocol.encode(command, args).ok_or_else(unsupported)
+    }
+}
+
+macro_rules! native_codes {
+    ($($command:ident),* $(,)?) => {
+        &[$((ReportByte::$command, ReportByte::$command as u8)),*]
+    };
+}
+
+pub static CLOUD_II_CORE_WIRELESS: Model = Model {
+    name: "HyperX Cloud II Core Wireless",
+    vendor_ids: &VENDOR_IDS,
+    product_ids: &[0x0995],
+    capabilities: Capabilities::all(),
+    // No firmware-gated features are known yet.
+    firmware_requirements: &[],
+    protocol: Protocol {
+        request_header: &[MAGIC_BYTE],
+        request_len: 0,
+        response_header: &[MAGIC_BYTE],
+        response_len: 8,
+        battery_offset: 2,
+        codes: native_codes!(
+            SetMonitorState,
+            SetPowerAutoOffTiming,
+            SetMicMuteState,
+            SetPlaybackMuteState,
+            SetMonitorVolume,
+            UpdateMicConnectionStatus,
+            UpdateMicMonitorStatus,
+            UpdateMicMuteStatus,
+            UpdateConnectedStatus,
+            UpdateChargingStatus,
+            UpdateBatteryStatus,
+            SetNoiseGateState,
+            GetDeviceInformation,
+            GetConnectedStatus,
+            GetMonitorState,
+            GetPowerAutoOffTiming,
+            GetMicMuteState,
+            GetPlaybackMuteState,
+            GetMonitorVolume,
+            GetBatteryLevel,
+            GetChargerState,
+            GetMicPlugState,
+            GetNoiseGateState,
+        ),
+    },
+};
+
+// Status changes are reported with the same codes as the matching queries.
+pub static CLOUD_II_WIRELESS: Model = Model {
+    name: "HyperX Cloud II Wireless",
+    vendor_ids: &VENDOR_IDS,
+    product_ids: &[0x1718, 0x018B, 0x0D93, 0x0696],
+    capabilities: Capabilities::empty()
+        .with(Capability::Connection)
+        .with(Capability::Battery)
+        .with(Capability::Charging)
+        .with(Capability::MicMute)
+        .with(Capability::MicPlug)
+        .with(Capability::Sidetone)
+        .with(Capability::SidetoneVolume)
+        .with(Capability::AutoOff),
+    firmware_requirements: &[],
+    protocol: Protocol {
+        request_header: &[0x06, 0xFF, 0xBB],
+        request_len: 62,
+        response_header: &[0x06, 0xFF, 0xBB],
+        response_len: 8,
+        battery_offset: 3,
+        codes: &[
+            (ReportByte::GetConnectedStatus, 1),
+            (ReportByte::GetBatteryLevel, 2),
+            (ReportByte::GetChargerState, 3),
+            (ReportByte::GetMicMuteState, 5),
+            (ReportByte::GetMonitorState, 6),
+            (ReportByte::GetPowerAutoOffTiming, 7),
+            (ReportByte::GetMicPlugState, 8),
+            (ReportByte::GetMonitorVolume, 11),
+            (ReportByte::SetMicMuteState, 32),
+            (ReportByte::SetMonitorState, 33),
+            (ReportByte::SetPowerAutoOffTiming, 34),
+            (ReportByte::SetMonitorVolume, 35),
+        ],
+    },
+};
+
+// Only answers queries, it never reports changes on its own.
+pub static CLOUD_ALPHA_WIRELESS: Model = Model {
+    name: "HyperX Cloud Alpha Wireless",
+    vendor_ids: &VENDOR_IDS,
+    product_ids: &[0x098D],
+    capabilities: Capabilities::empty()
+        .with(Capability::Battery)
+        .with(Capability::Charging)
+        .with(Capability::Sidetone)
+        .with(Capability::SidetoneVolume)
+        .with(Capability::AutoOff),
+    firmware_requirements: &[],
+    protocol: Protocol {
+        request_header: &[0x21, 0xBB],
+        request_len: 31,
+        response_header: &[0x21, 0xBB],
+        response_len: 8,
+        battery_offset: 0,
+        codes: &[
+            (ReportByte::GetBatteryLevel, 0x0B),
+            (ReportByte::GetChargerState, 0x0C),
+            (ReportByte::SetMonitorState, 0x10),
+            (ReportByte::SetMonitorVolume, 0x11),
+            (ReportByte::SetPowerAutoOffTiming, 0x12),
+        ],
+    },
+};
+
+/// Every supported model, in the order they are matched against attached devices.
+pub static MODELS: [&Model; 3] = [
+    &CLOUD_II_CORE_WIRELESS,
+    &CLOUD_II_WIRELESS,
+    &CLOUD_ALPHA_WIRELESS,
+];
+
+#[test]
+fn test_model_protocols() {
+    assert_eq!(
+        Model::find(0x03F0, 0x0995).map(|model| model.name),
+        Some(CLOUD_II_CORE_WIRELESS.name)
+    );
+    assert!(Model::find(0x1234, 0x0995).is_none());
+
+    let core = &CLOUD_II_CORE_WIRELESS;
+    assert_eq!(
+        core.encode(ReportByte::SetMicMuteState, &[1]).ok(),
+        Some(vec![MAGIC_BYTE, 3, 1])
+    );
+
+    let wireless = &CLOUD_II_WIRELESS;
+    let request = wireless
+        .encode(ReportByte::GetBatteryLevel, &[])
+        .ok()
+        .unwrap();
+    assert_eq!(request.len(), 62);
+    assert_eq!(request[..4], [0x06, 0xFF, 0xBB, 2]);
+    let reply = [0x06, 0xFF, 0xBB, 2, 0, 0, 0, 77];
+    assert!(matches!(
+        wireless.protocol.decode(&reply),
+        Ok((
+            ReportByte::GetBatteryLevel,
+            DeviceEvent::SetBatteryLevel(77)
+        ))
+    ));
+    assert!(matches!(
+        wireless.encode(ReportByte::SetNoiseGateState, &[1]),
+        Err(DeviceError::Unsupported(_, Capability::NoiseGate))
+    ));
+
+    let alpha = &CLOUD_ALPHA_WIRELESS;
+    assert!(matches!(
+        alpha.encode(ReportByte::SetMicMuteState, &[1]),
+        Err(DeviceError::Unsupported(_, Capability::MicMute))
+    ));
+    assert_eq!(Capabilities::all().iter().count(), Capability::ALL.len());
+
+    let gated = Model {
+        firmware_requirements: &[(
+            Capability::NoiseGate,
+            FirmwareVersion { major: 2, minor: 0 },
+        )],
+        ..CLOUD_II_CORE_WIRELESS.clone()
+    };
+    let has_noise_gate = |major, minor| {
+        gated
+            .capabilities_for(Some(FirmwareVersion { major, minor }))
+            .contains(Capability::NoiseGate)
+    };
+    assert!(!has_noise_gate(1, 9));
+    assert!(has_noise_gate(2, 0));
+    assert!(has_noise_gate(2, 1));
+    assert!(gated.capabilities_for(Some(FirmwareVersion { major: 1, minor: 9 })).contains(Capability::MicMute));
+    assert_eq!(gated.capabilities_for(None), CLOUD_II_CORE_WIRELESS.capabilities);
+}
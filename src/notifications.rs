@@ -0,0 +1,226 @@
+//! Desktop notifications for low battery and other headset events.
+//!
+//! [`Notifier`] decides what to announce from the stream of [`DeviceEvent`]s;
+//! [`send`] shows the result through the freedesktop notification service.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DeviceEvent, DeviceState};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Battery levels, in percent, to warn at.
+    pub battery_thresholds: Vec<u8>,
+    /// How far the level has to climb back above a threshold before it can fire again.
+    pub hysteresis: u8,
+    pub headset_disconnected: bool,
+    pub mic_unplugged: bool,
+    pub fully_charged: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            battery_thresholds: vec![20, 10, 5],
+            hysteresis: 3,
+            headset_disconnected: true,
+            mic_unplugged: true,
+            fully_charged: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    /// Freedesktop icon name.
+    pub icon: &'static str,
+    pub urgency: Urgency,
+}
+
+/// Turns headset events into notifications, firing each battery threshold once per crossing.
+#[derive(Debug)]
+pub struct Notifier {
+    config: NotificationConfig,
+    // One flag per threshold, cleared once it has fired
+    armed: Vec<bool>,
+    headset_connected: Option<bool>,
+    mic_connected: Option<bool>,
+    full_notified: bool,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> Self {
+        Notifier {
+            armed: vec![true; config.battery_thresholds.len()],
+            config,
+            headset_connected: None,
+            mic_connected: None,
+            full_notified: false,
+        }
+    }
+
+    /// Handles `event`, `state` being the headset state after it was applied.
+    pub fn handle(&mut self, event: &DeviceEvent, state: &DeviceState) -> Option<Notification> {
+        match *event {
+            DeviceEvent::ChargeLevel(level) | DeviceEvent::SetBatteryLevel(level) => {
+                self.battery(level, state.charging == Some(true))
+            }
+            DeviceEvent::Charging(charging) => {
+                if !charging {
+                    self.full_notified = false;
+                }
+                self.battery(state.battery_level, charging)
+            }
+            DeviceEvent::HeadsetConnected(connected) => {
+                let was_connected = self.headset_connected.replace(connected);
+                (self.config.headset_disconnected && !connected && was_connected == Some(true))
+                    .then(|| Notification {
+                        summary: "Headset disconnected".to_string(),
+                        body: format!("{} is off or out of range.", state.model),
+                        icon: "audio-headset",
+                        urgency: Urgency::Normal,
+                    })
+            }
+            DeviceEvent::MicConnected(connected) => {
+                let was_connected = self.mic_connected.replace(connected);
+                (self.config.mic_unplugged && !connected && was_connected == Some(true))
+                    .then(|| Notification {
+                        summary: "Microphone unplugged".to_string(),
+                        body: "The headset microphone was disconnected.".to_string(),
+                        icon: "microphone-sensitivity-muted",
+                        urgency: Urgency::Normal,
+                    })
+            }
+            _ => None,
+        }
+    }
+
+    fn battery(&mut self, level: u8, charging: bool) -> Option<Notification> {
+        for (threshold, armed) in self.config.battery_thresholds.iter().zip(&mut self.armed) {
+            if level >= threshold.saturating_add(self.config.hysteresis) {
+                *armed = true;
+            }
+        }
+
+        if charging {
+            if level >= 100 && self.config.fully_charged && !self.full_notified {
+                self.full_notified = true;
+                return Some(Notification {
+                    summary: "Headset fully charged".to_string(),
+                    body: "The battery is at 100%.".to_string(),
+                    icon: "battery-full-charged",
+                    urgency: Urgency::Low,
+                });
+            }
+            return None;
+        }
+
+        // Only the lowest threshold crossed is announced, the others are silenced with it.
+        let mut crossed = None;
+        for (&threshold, armed) in self.config.battery_thresholds.iter().zip(&mut self.armed) {
+            if *armed && level <= threshold {
+                *armed = false;
+                crossed = Some(crossed.map_or(threshold, |lowest: u8| lowest.min(threshold)));
+            }
+        }
+        let threshold = crossed?;
+        let critical = self.config.battery_thresholds.iter().min() == Some(&threshold);
+        Some(Notification {
+            summary: "Headset battery low".to_string(),
+            body: format!("{level}% remaining."),
+            icon: if critical { "battery-caution" } else { "battery-low" },
+            urgency: if critical { Urgency::Critical } else { Urgency::Normal },
+        })
+    }
+}
+
+/// Shows `notification` through `org.freedesktop.Notifications`, returning its id.
+#[cfg(target_os = "linux")]
+pub fn send(connection: &dbus::blocking::Connection, notification: &Notification) -> Result<u32, dbus::Error> {
+    use dbus::arg::{PropMap, RefArg, Variant};
+
+    let proxy = connection.with_proxy(
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        std::time::Duration::from_secs(5),
+    );
+    let mut hints = PropMap::new();
+    hints.insert(
+        "urgency".to_string(),
+        Variant(Box::new(notification.urgency as u8) as Box<dyn RefArg>),
+    );
+    let (id,): (u32,) = proxy.method_call(
+        "org.freedesktop.Notifications",
+        "Notify",
+        (
+            "HyperX",
+            0u32,
+            notification.icon,
+            notification.summary.as_str(),
+            notification.body.as_str(),
+            Vec::<String>::new(),
+            hints,
+            -1i32,
+        ),
+    )?;
+    Ok(id)
+}
+
+#[test]
+fn test_battery_thresholds() {
+    let mut notifier = Notifier::new(NotificationConfig::default());
+    let mut state = DeviceState {
+        charging: Some(false),
+        ..DeviceState::default()
+    };
+    let mut level = |notifier: &mut Notifier, level: u8| {
+        state.battery_level = level;
+        notifier
+            .handle(&DeviceEvent::ChargeLevel(level), &state)
+            .map(|notification| notification.urgency)
+    };
+
+    assert_eq!(level(&mut notifier, 25), None);
+    assert_eq!(level(&mut notifier, 20), Some(Urgency::Normal));
+    assert_eq!(level(&mut notifier, 19), None);
+    // Bouncing around a threshold doesn't fire it again until the level recovers.
+    assert_eq!(level(&mut notifier, 21), None);
+    assert_eq!(level(&mut notifier, 20), None);
+    assert_eq!(level(&mut notifier, 23), None);
+    assert_eq!(level(&mut notifier, 20), Some(Urgency::Normal));
+    // Skipping past several thresholds fires once.
+    assert_eq!(level(&mut notifier, 4), Some(Urgency::Critical));
+    assert_eq!(level(&mut notifier, 3), None);
+}
+
+#[test]
+fn test_quiet_while_charging() {
+    let mut notifier = Notifier::new(NotificationConfig::default());
+    let state = DeviceState {
+        charging: Some(true),
+        battery_level: 10,
+        headset_connected: Some(true),
+        ..DeviceState::default()
+    };
+    assert_eq!(notifier.handle(&DeviceEvent::ChargeLevel(10), &state), None);
+
+    let full = DeviceState {
+        battery_level: 100,
+        ..state.clone()
+    };
+    assert!(notifier.handle(&DeviceEvent::ChargeLevel(100), &full).is_some());
+    assert_eq!(notifier.handle(&DeviceEvent::ChargeLevel(100), &full), None);
+
+    assert_eq!(notifier.handle(&DeviceEvent::HeadsetConnected(true), &state), None);
+    assert!(notifier.handle(&DeviceEvent::HeadsetConnected(false), &state).is_some());
+}
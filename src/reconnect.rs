@@ -0,0 +1,159 @@
+//! A [`Headset`] that outlives the dongle being unplugged or `hyperxd` restarting.
+//!
+//! [`ReconnectingHeadset`] reopens the headset by itself, backing off while it
+//! stays away, and reports both transitions as events, so front-ends don't have
+//! to tell a lost device from a failed read by looking at error messages.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::headset::Headset;
+use crate::{DeviceError, DeviceEvent, DeviceInfo, DeviceSelector, DeviceState, Setting};
+
+/// Upper bound for the delay between two attempts to reopen the headset.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadsetEvent {
+    Device(DeviceEvent),
+    /// The dongle or the daemon went away. The state is cleared until it is back.
+    Disconnected,
+    /// The headset was opened again, which requests its whole state.
+    Reconnected,
+}
+
+type Open = Box<dyn FnMut() -> Result<Headset, DeviceError> + Send>;
+
+pub struct ReconnectingHeadset {
+    open: Open,
+    headset: Option<Headset>,
+    retry_interval: Duration,
+    delay: Duration,
+    next_attempt: Instant,
+}
+
+impl ReconnectingHeadset {
+    /// Doesn't block: the headset is opened by the first [`wait_for_updates`](Self::wait_for_updates),
+    /// which then reports [`HeadsetEvent::Reconnected`].
+    pub fn new(selector: DeviceSelector, retry_interval: Duration) -> Self {
+        Self::with_opener(move || Headset::open(&selector), retry_interval)
+    }
+
+    /// Like [`ReconnectingHeadset::new`], getting the headset from `open` instead of [`Headset::open`].
+    pub fn with_opener<F>(open: F, retry_interval: Duration) -> Self
+    where
+        F: FnMut() -> Result<Headset, DeviceError> + Send + 'static,
+    {
+        ReconnectingHeadset {
+            open: Box::new(open),
+            headset: None,
+            retry_interval,
+            delay: retry_interval,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.headset.is_some()
+    }
+
+    /// The headset state, or the default one while it's away.
+    pub fn state(&self) -> DeviceState {
+        self.headset.as_ref().map(Headset::state).unwrap_or_default()
+    }
+
+    /// Waits up to `duration` for a report, or for the next attempt to reopen the headset.
+    ///
+    /// Errors are either passed on from a read that failed without losing the
+    /// headset, or the reason the last reopen attempt failed.
+    pub fn wait_for_updates(&mut self, duration: Duration) -> Result<HeadsetEvent, DeviceError> {
+        let Some(headset) = self.headset.as_mut() else {
+            return self.reconnect(duration);
+        };
+        match headset.wait_for_updates(duration) {
+            Ok(event) => Ok(HeadsetEvent::Device(event)),
+            Err(error @ DeviceError::NoResponse())
+            | Err(error @ DeviceError::UnknownResponse(_, _))
+            | Err(error @ DeviceError::UnknownCommand(_)) => Err(error),
+            Err(_) => {
+                self.headset = None;
+                self.delay = self.retry_interval;
+                self.next_attempt = Instant::now();
+                Ok(HeadsetEvent::Disconnected)
+            }
+        }
+    }
+
+    fn reconnect(&mut self, duration: Duration) -> Result<HeadsetEvent, DeviceError> {
+        let wait = self.next_attempt.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait.min(duration));
+            if wait > duration {
+                return Err(DeviceError::NoResponse());
+            }
+        }
+
+        match (self.open)() {
+            Ok(headset) => {
+                self.headset = Some(headset);
+                self.delay = self.retry_interval;
+                Ok(HeadsetEvent::Reconnected)
+            }
+            Err(error) => {
+                self.next_attempt = Instant::now() + self.delay;
+                self.delay = (self.delay * 2).min(MAX_RETRY_DELAY.max(self.retry_interval));
+                Err(error)
+            }
+        }
+    }
+
+    fn headset(&mut self) -> Result<&mut Headset, DeviceError> {
+        self.headset.as_mut().ok_or(DeviceError::NoDeviceFound())
+    }
+
+    pub fn refresh_state(&mut self) -> Result<(), DeviceError> {
+        self.headset()?.refresh_state()
+    }
+
+    pub fn device_info(&mut self) -> Result<DeviceInfo, DeviceError> {
+        self.headset()?.device_info()
+    }
+
+    pub fn apply(&mut self, setting: Setting) -> Result<(), DeviceError> {
+        self.headset()?.apply(setting)
+    }
+}
+
+#[test]
+fn test_reconnects_after_unplug() {
+    use crate::emulator::{Emulator, HeadsetState, Script, ScriptEvent};
+    use crate::Device;
+
+    let script = Script::new()
+        .at(Duration::from_millis(100), ScriptEvent::Unplug)
+        .at(Duration::from_millis(150), ScriptEvent::Replug);
+    let emulator = Emulator::new(HeadsetState::default(), script);
+    let mut opened = 0;
+    let open = move || {
+        opened += 1;
+        // The first attempt after the unplug doesn't find the dongle.
+        match opened {
+            2 => Err(DeviceError::NoDeviceFound()),
+            _ => Ok(Headset::Direct(Device::with_transport(emulator.clone()))),
+        }
+    };
+    let mut headset = ReconnectingHeadset::with_opener(open, Duration::from_millis(100));
+
+    let next = |headset: &mut ReconnectingHeadset| loop {
+        match headset.wait_for_updates(Duration::from_millis(50)) {
+            Ok(HeadsetEvent::Device(_)) | Err(DeviceError::NoResponse()) => {}
+            result => break result,
+        }
+    };
+    assert!(matches!(next(&mut headset), Ok(HeadsetEvent::Reconnected)));
+    assert!(matches!(next(&mut headset), Ok(HeadsetEvent::Disconnected)));
+    assert_eq!(headset.state(), DeviceState::default());
+    assert!(matches!(next(&mut headset), Err(DeviceError::NoDeviceFound())));
+    assert!(matches!(next(&mut headset), Ok(HeadsetEvent::Reconnected)));
+    assert!(headset.is_connected());
+}
@@ -0,0 +1,148 @@
+//! Machine-readable snapshot of a headset, printed by `cli_app get --format`.
+//!
+//! The field set only grows; renaming or removing a field bumps [`REPORT_VERSION`].
+//! Anything unknown, unsupported or unreadable while the headset is off is `null`.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::Estimate;
+use crate::{Capabilities, Capability, DeviceState};
+
+pub const REPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub version: u32,
+    pub model: String,
+    pub capabilities: Capabilities,
+    pub headset_connected: Option<bool>,
+    pub battery_level: Option<u8>,
+    pub charging: Option<bool>,
+    pub mic_connected: Option<bool>,
+    pub mic_muted: Option<bool>,
+    pub mic_monitored: Option<bool>,
+    pub monitor_volume: Option<i8>,
+    /// Minutes, 0 when disabled.
+    pub timeout: Option<u8>,
+    pub noise_gate: Option<bool>,
+    pub playback_muted: Option<bool>,
+    /// `major.minor`
+    pub firmware_version: Option<String>,
+    pub hardware_revision: Option<u8>,
+    /// Minutes until the battery is empty, estimated from the battery history.
+    pub time_remaining: Option<u64>,
+    /// Minutes until the battery is full, estimated from the battery history.
+    pub time_to_full: Option<u64>,
+}
+
+impl From<&DeviceState> for Report {
+    fn from(state: &DeviceState) -> Self {
+        Report {
+            version: REPORT_VERSION,
+            model: state.model.clone(),
+            capabilities: state.capabilities,
+            headset_connected: state.headset_connected,
+            battery_level: state.known(Capability::Battery, state.battery_level),
+            charging: state.known(Capability::Charging, state.charging).flatten(),
+            mic_connected: state.known(Capability::MicPlug, state.mic_connected).flatten(),
+            mic_muted: state.known(Capability::MicMute, state.muted).flatten(),
+            mic_monitored: state.known(Capability::Sidetone, state.mic_monitored).flatten(),
+            monitor_volume: state.known(Capability::SidetoneVolume, state.monitor_volume()),
+            timeout: state.known(Capability::AutoOff, state.timeout),
+            noise_gate: state.known(Capability::NoiseGate, state.noise_gate).flatten(),
+            playback_muted: state.known(Capability::PlaybackMute, state.playback_muted).flatten(),
+            firmware_version: state.firmware_version.map(|version| version.to_string()),
+            hardware_revision: state.hardware_revision,
+            time_remaining: None,
+            time_to_full: None,
+        }
+    }
+}
+
+impl Report {
+    /// Fills in the time estimates.
+    pub fn with_estimate(mut self, estimate: Option<&Estimate>) -> Self {
+        let minutes = |duration: Option<Duration>| duration.map(|duration| duration.as_secs() / 60);
+        self.time_remaining = minutes(estimate.and_then(|estimate| estimate.time_remaining));
+        self.time_to_full = minutes(estimate.and_then(|estimate| estimate.time_to_full));
+        self
+    }
+
+    /// `HYPERX_<FIELD>=value` lines for `eval` or `source`: nulls are empty,
+    /// strings single-quoted and lists comma separated.
+    pub fn to_env(&self) -> String {
+        let serde_json::Value::Object(fields) = serde_json::to_value(self).unwrap() else {
+            unreachable!("a report serializes as an object");
+        };
+        fn quote(text: &str) -> String {
+            format!("'{}'", text.replace('\'', r"'\''"))
+        }
+        fields
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(text) => quote(text),
+                    serde_json::Value::Array(items) => {
+                        let items: Vec<String> = items
+                            .iter()
+                            .map(|item| item.as_str().map_or_else(|| item.to_string(), str::to_string))
+                            .collect();
+                        quote(&items.join(","))
+                    }
+                    value => value.to_string(),
+                };
+                format!("HYPERX_{}={value}\n", name.to_uppercase())
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_report_nulls() {
+    let mut state = DeviceState {
+        model: "HyperX Cloud II Core Wireless".to_string(),
+        capabilities: crate::MODELS[0].capabilities,
+        headset_connected: Some(false),
+        battery_level: 0,
+        monitor_volume: -3i8 as u8,
+        // Left over from before the headset went off.
+        charging: Some(true),
+        muted: Some(true),
+        mic_monitored: Some(false),
+        ..DeviceState::default()
+    };
+    let json = serde_json::to_value(Report::from(&state)).unwrap();
+    assert_eq!(json["version"], REPORT_VERSION);
+    for field in ["battery_level", "charging", "mic_muted", "mic_monitored", "monitor_volume"] {
+        assert!(json[field].is_null(), "{field}");
+    }
+
+    state.headset_connected = Some(true);
+    state.battery_level = 55;
+    let report = Report::from(&state);
+    assert_eq!(report.monitor_volume, Some(-3));
+    let env = report.to_env();
+    assert!(env.contains("HYPERX_BATTERY_LEVEL=55\n"));
+    assert!(env.contains("HYPERX_CHARGING=true\n"));
+    assert!(env.contains("HYPERX_MIC_CONNECTED=\n"));
+    assert!(env.contains("HYPERX_MODEL='HyperX Cloud II Core Wireless'\n"));
+}
+
+#[test]
+fn test_report_without_connection_status() {
+    use crate::models::CLOUD_ALPHA_WIRELESS;
+    use crate::{Device, MockTransport};
+
+    let mock = MockTransport::new();
+    let mut device = Device::with_model(mock.clone(), &CLOUD_ALPHA_WIRELESS);
+    assert_eq!(Report::from(&device.state()).headset_connected, None);
+
+    mock.push_report(&[0x21, 0xBB, 0x0B, 70, 0, 0, 0, 0]);
+    assert!(matches!(device.query_battery_level(), Ok(70)));
+    let report = Report::from(&device.state());
+    assert_eq!(report.headset_connected, Some(true));
+    assert_eq!(report.battery_level, Some(70));
+}
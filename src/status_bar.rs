@@ -0,0 +1,301 @@
+//! Status bar output for `hyperx_bar`: Waybar custom modules, the i3bar/swaybar
+//! protocol and Polybar.
+//!
+//! [`StatusBar`] turns the headset state into a [`Status`] with CSS-style
+//! classes, which each bar then gets in its own format. Clicks only come back
+//! through the i3bar protocol and i3blocks, as [`ClickEvent`]s on stdin that
+//! [`ClickActions`] turn into settings.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::BarConfig;
+use crate::template::Template;
+use crate::{DeviceError, DeviceState, Setting};
+
+/// `name` of the i3bar block, which click events are matched against.
+pub const BLOCK_NAME: &str = "hyperx";
+
+/// Header opening the i3bar protocol, followed by the endless array of status lines.
+pub const I3BAR_HEADER: &str = "{\"version\":1,\"click_events\":true}\n[\n[]";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub text: String,
+    pub tooltip: String,
+    /// `disconnected`, or any of `charging`, `warning` or `critical`, `muted`,
+    /// `mic-unplugged` and `playback-muted`, in that order.
+    pub classes: Vec<&'static str>,
+    pub percentage: Option<u8>,
+}
+
+impl Status {
+    /// Color of the first class that has one in `colors`.
+    pub fn color<'a>(&self, colors: &'a BTreeMap<String, String>) -> Option<&'a str> {
+        self.classes
+            .iter()
+            .find_map(|class| colors.get(*class))
+            .map(String::as_str)
+    }
+
+    /// A line for a Waybar custom module with `return-type: json`.
+    pub fn to_waybar(&self) -> String {
+        let mut module = json!({
+            "text": self.text,
+            "tooltip": self.tooltip,
+            "class": self.classes,
+        });
+        if let Some(percentage) = self.percentage {
+            module["percentage"] = percentage.into();
+        }
+        module.to_string()
+    }
+
+    /// One i3bar status line, to print after [`I3BAR_HEADER`].
+    pub fn to_i3bar(&self, colors: &BTreeMap<String, String>) -> String {
+        let mut block = json!({
+            "name": BLOCK_NAME,
+            "full_text": self.text,
+            "urgent": self.classes.contains(&"critical"),
+        });
+        if let Some(color) = self.color(colors) {
+            block["color"] = color.into();
+        }
+        format!(",[{block}]")
+    }
+
+    /// Text with Polybar color tags.
+    pub fn to_polybar(&self, colors: &BTreeMap<String, String>) -> String {
+        match self.color(colors) {
+            Some(color) if !self.text.is_empty() => format!("%{{F{color}}}{}%{{F-}}", self.text),
+            _ => self.text.clone(),
+        }
+    }
+}
+
+pub struct StatusBar {
+    template: Template,
+    tooltip: Template,
+    warning: u8,
+    critical: u8,
+}
+
+impl StatusBar {
+    pub fn new(config: &BarConfig) -> Result<Self, DeviceError> {
+        Ok(StatusBar {
+            template: Template::parse(&config.template)?,
+            tooltip: Template::parse(&config.tooltip)?,
+            warning: config.warning,
+            critical: config.critical,
+        })
+    }
+
+    pub fn status(&self, state: &DeviceState) -> Status {
+        let connected = state.headset_connected == Some(true);
+        let mut classes = Vec::new();
+        if !connected {
+            classes.push("disconnected");
+        } else if state.charging == Some(true) {
+            classes.push("charging");
+        } else if state.battery_level <= self.critical {
+            classes.push("critical");
+        } else if state.battery_level <= self.warning {
+            classes.push("warning");
+        }
+        if connected {
+            if state.muted == Some(true) {
+                classes.push("muted");
+            }
+            if state.mic_connected == Some(false) {
+                classes.push("mic-unplugged");
+            }
+            if state.playback_muted == Some(true) {
+                classes.push("playback-muted");
+            }
+        }
+        Status {
+            text: self.template.render(state),
+            tooltip: self.tooltip.render(state),
+            classes,
+            percentage: connected.then_some(state.battery_level),
+        }
+    }
+}
+
+// Idle timeouts cycled through, in minutes, the same as in the tray menu
+const TIMEOUTS: [u8; 4] = [0, 10, 20, 30];
+// Monitor volume range of the headset
+const MONITOR_VOLUMES: std::ops::RangeInclusive<i8> = -5..=5;
+
+/// What a mouse button does to the headset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickAction {
+    None,
+    ToggleMute,
+    ToggleMonitoring,
+    TogglePlaybackMute,
+    ToggleNoiseGate,
+    VolumeUp,
+    VolumeDown,
+    CycleTimeout,
+}
+
+impl ClickAction {
+    /// The setting carrying out the action on `state`, if the headset supports
+    /// it and it would change anything.
+    pub fn setting(self, state: &DeviceState) -> Option<Setting> {
+        let volume = state.monitor_volume();
+        let setting = match self {
+            ClickAction::None => return None,
+            ClickAction::ToggleMute => Setting::MicMuted(!state.muted?),
+            ClickAction::ToggleMonitoring => Setting::MicMonitored(!state.mic_monitored?),
+            ClickAction::TogglePlaybackMute => Setting::PlaybackMuted(!state.playback_muted?),
+            ClickAction::ToggleNoiseGate => Setting::NoiseGate(!state.noise_gate?),
+            ClickAction::VolumeUp if volume < *MONITOR_VOLUMES.end() => Setting::MonitorVolume(volume + 1),
+            ClickAction::VolumeDown if volume > *MONITOR_VOLUMES.start() => Setting::MonitorVolume(volume - 1),
+            ClickAction::VolumeUp | ClickAction::VolumeDown => return None,
+            ClickAction::CycleTimeout => {
+                let next = TIMEOUTS.iter().find(|&&minutes| minutes > state.timeout);
+                Setting::Timeout(*next.unwrap_or(&TIMEOUTS[0]))
+            }
+        };
+        state.capabilities.contains(setting.capability()).then_some(setting)
+    }
+}
+
+/// Action of each mouse button.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClickActions {
+    pub left: ClickAction,
+    pub middle: ClickAction,
+    pub right: ClickAction,
+    pub scroll_up: ClickAction,
+    pub scroll_down: ClickAction,
+}
+
+impl Default for ClickActions {
+    fn default() -> Self {
+        ClickActions {
+            left: ClickAction::ToggleMute,
+            middle: ClickAction::CycleTimeout,
+            right: ClickAction::ToggleMonitoring,
+            scroll_up: ClickAction::VolumeUp,
+            scroll_down: ClickAction::VolumeDown,
+        }
+    }
+}
+
+impl ClickActions {
+    pub fn action(&self, button: u8) -> ClickAction {
+        match button {
+            1 => self.left,
+            2 => self.middle,
+            3 => self.right,
+            4 => self.scroll_up,
+            5 => self.scroll_down,
+            _ => ClickAction::None,
+        }
+    }
+}
+
+/// A click on a block, as sent by i3bar, swaybar and i3blocks.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ClickEvent {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 1 left, 2 middle, 3 right, 4 and 5 scroll up and down.
+    pub button: u8,
+}
+
+impl ClickEvent {
+    /// Parses one line of the click event stream: a JSON object, after a `[`
+    /// or a comma in the i3bar protocol, or a bare button number like
+    /// `BLOCK_BUTTON`. Returns `None` for anything else.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim().trim_start_matches(['[', ',']).trim_start();
+        if let Ok(button) = line.parse() {
+            return Some(ClickEvent { name: None, button });
+        }
+        serde_json::from_str(line).ok()
+    }
+}
+
+#[test]
+fn test_status_classes() {
+    let bar = StatusBar::new(&BarConfig::default()).unwrap();
+    let mut state = DeviceState {
+        capabilities: crate::MODELS[0].capabilities,
+        headset_connected: Some(true),
+        battery_level: 8,
+        charging: Some(false),
+        muted: Some(true),
+        ..DeviceState::default()
+    };
+    let status = bar.status(&state);
+    assert_eq!(status.classes, vec!["critical", "muted"]);
+    assert_eq!(status.text, "🎧 8% 🔇");
+    assert_eq!(
+        status.to_polybar(&BarConfig::default().colors),
+        "%{F#ff5555}🎧 8% 🔇%{F-}"
+    );
+    let waybar: serde_json::Value = serde_json::from_str(&status.to_waybar()).unwrap();
+    assert_eq!(waybar["class"], json!(["critical", "muted"]));
+    assert_eq!(waybar["percentage"], 8);
+
+    state.headset_connected = Some(false);
+    let status = bar.status(&state);
+    assert_eq!(status.classes, vec!["disconnected"]);
+    assert_eq!(status.text, "");
+    assert_eq!(status.percentage, None);
+}
+
+#[test]
+fn test_parse_click_events() {
+    assert_eq!(ClickEvent::parse("["), None);
+    let event = ClickEvent::parse(r#"{"name":"hyperx","button":1,"x":10}"#).unwrap();
+    assert_eq!(event.name.as_deref(), Some(BLOCK_NAME));
+    assert_eq!(event.button, 1);
+    assert_eq!(ClickEvent::parse(r#",{"name":"hyperx","button":5}"#).unwrap().button, 5);
+    assert_eq!(ClickEvent::parse(r#"[{"button":3}"#).unwrap().button, 3);
+    assert_eq!(ClickEvent::parse("11\n").unwrap().button, 11);
+    assert_eq!(ClickEvent::parse(""), None);
+}
+
+#[test]
+fn test_click_actions() {
+    let actions = ClickActions::default();
+    let mut state = DeviceState {
+        capabilities: crate::MODELS[0].capabilities,
+        muted: Some(true),
+        timeout: 30,
+        monitor_volume: 5,
+        ..DeviceState::default()
+    };
+    assert_eq!(actions.action(1).setting(&state), Some(Setting::MicMuted(false)));
+    assert_eq!(actions.action(2).setting(&state), Some(Setting::Timeout(0)));
+    // Unknown state and the ends of the volume range leave the headset alone.
+    assert_eq!(actions.action(3).setting(&state), None);
+    assert_eq!(actions.action(4).setting(&state), None);
+    assert_eq!(actions.action(5).setting(&state), Some(Setting::MonitorVolume(4)));
+    assert_eq!(actions.action(11).setting(&state), None);
+
+    state.timeout = 12;
+    assert_eq!(actions.action(2).setting(&state), Some(Setting::Timeout(20)));
+
+    // Each click of a batch starts from where the previous one left the state.
+    state.monitor_volume = 0;
+    for volume in 1..=3 {
+        let setting = actions.action(4).setting(&state).unwrap();
+        assert_eq!(setting, Setting::MonitorVolume(volume));
+        setting.apply_to(&mut state);
+    }
+    Setting::MonitorVolume(-1).apply_to(&mut state);
+    assert_eq!(actions.action(5).setting(&state), Some(Setting::MonitorVolume(-2)));
+    let actions: ClickActions = toml::from_str("left = \"toggle_monitoring\"").unwrap();
+    assert_eq!(actions.action(1), ClickAction::ToggleMonitoring);
+    assert_eq!(actions.action(5), ClickAction::VolumeDown);
+}
@@ -0,0 +1,425 @@
+//! Small template language for status lines, shared by the output binaries.
+//!
+//! Text is copied as is, except for placeholders in braces:
+//!
+//! - `{battery}` is the value of a field, see [`FIELDS`].
+//! - `{battery|??}` prints `??` instead when the value is unknown. Without a
+//!   fallback an unknown value prints nothing.
+//! - `{battery:>3}` pads to 3 characters, aligned with `<`, `>` or `^`. Numbers
+//!   go right and everything else left when no alignment is given. A fallback
+//!   comes first: `{battery|-:>3}`.
+//! - `{charging?⚡}` and `{muted?🔇:🎤}` print the first branch when the field is
+//!   true, non-zero or non-empty and the second one, if any, otherwise, unknown
+//!   values included. Branches are templates themselves and may nest placeholders.
+//!
+//! `{{` and `}}` stand for literal braces, and `::` for a colon in a branch:
+//! `{muted?a}}b::c}` prints `a}b:c`. In a branch, `}}` is a brace only when the
+//! rest of the template still closes every open placeholder, so
+//! `{connected?{muted?🔇}}` closes both conditions.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::report::Report;
+use crate::{DeviceError, DeviceState};
+
+/// Fields every template can use.
+pub const FIELDS: [&str; 12] = [
+    "battery",
+    "charging",
+    "connected",
+    "mic_connected",
+    "muted",
+    "monitoring",
+    "monitor_volume",
+    "timeout",
+    "noise_gate",
+    "playback_muted",
+    "model",
+    "firmware",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Unknown,
+    Flag(bool),
+    Number(i64),
+    Text(String),
+}
+
+impl Value {
+    fn is_set(&self) -> bool {
+        match self {
+            Value::Unknown => false,
+            Value::Flag(flag) => *flag,
+            Value::Number(number) => *number != 0,
+            Value::Text(text) => !text.is_empty(),
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Unknown, Into::into)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(flag: bool) -> Self {
+        Value::Flag(flag)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(number: u8) -> Self {
+        Value::Number(number.into())
+    }
+}
+
+impl From<i8> for Value {
+    fn from(number: i8) -> Self {
+        Value::Number(number.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+fn field(report: &Report, name: &str) -> Value {
+    match name {
+        "battery" => report.battery_level.into(),
+        "charging" => report.charging.into(),
+        "connected" => report.headset_connected.into(),
+        "mic_connected" => report.mic_connected.into(),
+        "muted" => report.mic_muted.into(),
+        "monitoring" => report.mic_monitored.into(),
+        "monitor_volume" => report.monitor_volume.into(),
+        "timeout" => report.timeout.into(),
+        "noise_gate" => report.noise_gate.into(),
+        "playback_muted" => report.playback_muted.into(),
+        "model" => report.model.clone().into(),
+        "firmware" => report.firmware_version.clone().into(),
+        _ => Value::Unknown,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field {
+        name: String,
+        fallback: String,
+        align: Option<Align>,
+        width: usize,
+    },
+    Condition {
+        name: String,
+        then: Vec<Part>,
+        otherwise: Vec<Part>,
+    },
+}
+
+impl Part {
+    fn field(name: String, fallback: String, align: Option<Align>, width: usize) -> Part {
+        Part::Field {
+            name,
+            fallback,
+            align,
+            width,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses `source`, which may only use [`FIELDS`].
+    pub fn parse(source: &str) -> Result<Self, DeviceError> {
+        Self::parse_with(source, &[])
+    }
+
+    /// Like [`Template::parse`], also accepting the `extra` fields a binary
+    /// passes to [`Template::render_with`].
+    pub fn parse_with(source: &str, extra: &[&str]) -> Result<Self, DeviceError> {
+        let mut parser = Parser {
+            chars: source.chars().peekable(),
+            extra,
+            depth: 0,
+        };
+        let (parts, _) = parser.parts(&[])?;
+        Ok(Template {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn render(&self, state: &DeviceState) -> String {
+        self.render_with(state, &[])
+    }
+
+    /// Renders with `extra` fields on top of [`FIELDS`].
+    pub fn render_with(&self, state: &DeviceState, extra: &[(&str, Value)]) -> String {
+        let report = Report::from(state);
+        let lookup = |name: &str| match extra.iter().find(|(extra_name, _)| *extra_name == name) {
+            Some((_, value)) => value.clone(),
+            None => field(&report, name),
+        };
+        let mut output = String::new();
+        render_parts(&self.parts, &lookup, &mut output);
+        output
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = DeviceError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::parse(source)
+    }
+}
+
+fn render_parts(parts: &[Part], lookup: &dyn Fn(&str) -> Value, output: &mut String) {
+    for part in parts {
+        match part {
+            Part::Text(text) => output.push_str(text),
+            Part::Field {
+                name,
+                fallback,
+                align,
+                width,
+            } => {
+                let value = lookup(name);
+                let align = align.unwrap_or(match value {
+                    Value::Number(_) => Align::Right,
+                    _ => Align::Left,
+                });
+                let text = match value {
+                    Value::Unknown => fallback.clone(),
+                    Value::Flag(flag) => flag.to_string(),
+                    Value::Number(number) => number.to_string(),
+                    Value::Text(text) => text,
+                };
+                let padding = width.saturating_sub(text.chars().count());
+                let (left, right) = match align {
+                    Align::Left => (0, padding),
+                    Align::Right => (padding, 0),
+                    Align::Center => (padding / 2, padding - padding / 2),
+                };
+                output.extend(std::iter::repeat_n(' ', left));
+                output.push_str(&text);
+                output.extend(std::iter::repeat_n(' ', right));
+            }
+            Part::Condition {
+                name,
+                then,
+                otherwise,
+            } => {
+                let branch = if lookup(name).is_set() { then } else { otherwise };
+                render_parts(branch, lookup, output);
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    extra: &'a [&'a str],
+    // Placeholders opened and not closed yet
+    depth: usize,
+}
+
+fn error(message: impl Into<String>) -> DeviceError {
+    DeviceError::InvalidTemplate(message.into())
+}
+
+impl Parser<'_> {
+    /// Parses up to one of `terminators` outside of braces, returning the one it stopped at.
+    fn parts(&mut self, terminators: &[char]) -> Result<(Vec<Part>, Option<char>), DeviceError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.chars.next() else {
+                if !terminators.is_empty() {
+                    return Err(error("unclosed {"));
+                }
+                break;
+            };
+            let in_branch = !terminators.is_empty();
+            let escaped = match c {
+                '{' => self.chars.peek() == Some(&'{'),
+                '}' => self.chars.peek() == Some(&'}') && (!in_branch || self.closes_after_pair()),
+                ':' => in_branch && self.chars.peek() == Some(&':'),
+                _ => false,
+            };
+            if escaped {
+                self.chars.next();
+                text.push(c);
+                continue;
+            }
+            if terminators.contains(&c) {
+                if !text.is_empty() {
+                    parts.push(Part::Text(text));
+                }
+                return Ok((parts, Some(c)));
+            }
+            match c {
+                '{' => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    self.depth += 1;
+                    parts.push(self.placeholder()?);
+                    self.depth -= 1;
+                }
+                '}' => return Err(error("unmatched }, write }} for a literal one")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok((parts, None))
+    }
+
+    /// Whether the text after the `}}` at hand has enough closing braces left
+    /// for the open placeholders, so the pair can be a literal brace. Doubled
+    /// braces further on are taken for literal ones.
+    fn closes_after_pair(&self) -> bool {
+        let mut open = self.depth as isize;
+        let mut rest = self.chars.clone().skip(1).peekable();
+        while let Some(c) = rest.next() {
+            if matches!(c, '{' | '}') && rest.next_if_eq(&c).is_some() {
+                continue;
+            }
+            match c {
+                '{' => open += 1,
+                '}' => open -= 1,
+                _ => {}
+            }
+        }
+        open <= 0
+    }
+
+    fn placeholder(&mut self) -> Result<Part, DeviceError> {
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            name.push(c);
+            self.chars.next();
+        }
+        if !FIELDS.contains(&name.as_str()) && !self.extra.contains(&name.as_str()) {
+            return Err(error(format!("unknown field {name:?}")));
+        }
+
+        match self.chars.next() {
+            Some('?') => {
+                let (then, end) = self.parts(&[':', '}'])?;
+                let otherwise = match end {
+                    Some(':') => self.parts(&['}'])?.0,
+                    _ => Vec::new(),
+                };
+                Ok(Part::Condition { name, then, otherwise })
+            }
+            Some('|') => {
+                let mut fallback = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('}') => return Ok(Part::field(name, fallback, None, 0)),
+                        Some(':') => return self.spec(name, fallback),
+                        Some(c) => fallback.push(c),
+                        None => return Err(error("unclosed {")),
+                    }
+                }
+            }
+            Some(':') => self.spec(name, String::new()),
+            Some('}') => Ok(Part::field(name, String::new(), None, 0)),
+            Some(c) => Err(error(format!("unexpected {c:?} after {name:?}"))),
+            None => Err(error("unclosed {")),
+        }
+    }
+
+    fn spec(&mut self, name: String, fallback: String) -> Result<Part, DeviceError> {
+        let align = match self.chars.peek() {
+            Some('<') => Some(Align::Left),
+            Some('>') => Some(Align::Right),
+            Some('^') => Some(Align::Center),
+            _ => None,
+        };
+        if align.is_some() {
+            self.chars.next();
+        }
+        let mut width = String::new();
+        while let Some(c) = self.chars.next_if(char::is_ascii_digit) {
+            width.push(c);
+        }
+        if self.chars.next() != Some('}') {
+            return Err(error(format!("bad padding for {name:?}, expected e.g. :>3")));
+        }
+        let width = width.parse().map_err(|_| error(format!("missing width for {name:?}")))?;
+        Ok(Part::field(name, fallback, align, width))
+    }
+}
+
+#[test]
+fn test_render_template() {
+    let mut state = DeviceState {
+        capabilities: crate::MODELS[0].capabilities,
+        headset_connected: Some(true),
+        battery_level: 7,
+        charging: Some(true),
+        muted: Some(false),
+        ..DeviceState::default()
+    };
+    let template: Template = "[{battery:>3}%] {charging?⚡}{muted?🔇:🎤} {noise_gate|n/a} {{x}}"
+        .parse()
+        .unwrap();
+    assert_eq!(template.render(&state), "[  7%] ⚡🎤 n/a {x}");
+
+    // Readings left from before the headset went off are unknown.
+    state.headset_connected = Some(false);
+    assert_eq!(template.render(&state), "[   %] 🎤 n/a {x}");
+
+    let nested = Template::parse_with("{connected?{muted?M:{icon}}:off}", &["icon"]).unwrap();
+    assert_eq!(nested.render_with(&state, &[("icon", "I".into())]), "off");
+    state.headset_connected = Some(true);
+    assert_eq!(nested.render_with(&state, &[("icon", "I".into())]), "I");
+
+    assert!(Template::parse("{nope}").is_err());
+    assert!(Template::parse("{battery").is_err());
+    assert!(Template::parse("{battery:>}").is_err());
+    assert!(Template::parse("}").is_err());
+
+    // Escapes win over the end of a branch.
+    let escaped = Template::parse("{muted?a}}b::c:{{d}").unwrap();
+    assert_eq!(escaped.render(&state), "{d");
+    state.muted = Some(true);
+    assert_eq!(escaped.render(&state), "a}b:c");
+    let closing = Template::parse("{connected?{muted?M}}").unwrap();
+    assert_eq!(closing.render(&state), "M");
+}
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use hidapi::{HidDevice, HidError};
+
+/// Raw report I/O used by [`Device`](crate::Device).
+///
+/// The hidapi backend talks to the dongle, [`MockTransport`] replays scripted
+/// reports so the library can be exercised without a headset.
+pub trait Transport: std::fmt::Debug + Send {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError>;
+
+    /// Reads one report into `buf`, returning 0 when nothing arrived within `timeout` ms.
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError>;
+
+    fn product(&self) -> Result<Option<String>, HidError> {
+        Ok(None)
+    }
+
+    fn manufacturer(&self) -> Result<Option<String>, HidError> {
+        Ok(None)
+    }
+
+    fn serial_number(&self) -> Result<Option<String>, HidError> {
+        Ok(None)
+    }
+}
+
+impl Transport for HidDevice {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        HidDevice::write(self, data)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize, HidError> {
+        HidDevice::read_timeout(self, buf, timeout)
+    }
+
+    fn product(&self) -> Result<Option<String>, HidError> {
+        self.get_product_string()
+    }
+
+    fn manufacturer(&self) -> Result<Option<String>, HidError> {
+        self.get_manufacturer_string()
+    }
+
+    fn serial_number(&self) -> Result<Option<String>, HidError> {
+        self.get_serial_number_string()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    reads: VecDeque<Result<Vec<u8>, String>>,
+    writes: Vec<Vec<u8>>,
+    write_error: Option<String>,
+}
+
+/// In-memory transport that returns queued reports and records every write.
+///
+/// Clones share the same queues, so a test can keep one handle while the
+/// [`Device`](crate::Device) owns another.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a report to be returned by the next read.
+    pub fn push_report(&self, report: &[u8]) {
+        self.state
+            .lock()
+            .unwrap()
+            .reads
+            .push_back(Ok(report.to_vec()));
+    }
+
+    /// Queues a read that fails with a hidapi error carrying `message`.
+    pub fn push_error(&self, message: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .reads
+            .push_back(Err(message.to_string()));
+    }
+
+    /// Makes every following write fail with `message`, or succeed again with `None`.
+    pub fn fail_writes(&self, message: Option<&str>) {
+        self.state.lock().unwrap().write_error = message.map(str::to_string);
+    }
+
+    /// Every report written so far, oldest first.
+    pub fn writes(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().writes.clone()
+    }
+
+    pub fn clear_writes(&self) {
+        self.state.lock().unwrap().writes.clear();
+    }
+
+    pub fn pending_reads(&self) -> usize {
+        self.state.lock().unwrap().reads.len()
+    }
+}
+
+impl Transport for MockTransport {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(message) = &state.write_error {
+            return Err(HidError::HidApiError {
+                message: message.clone(),
+            });
+        }
+        state.writes.push(data.to_vec());
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], _timeout: i32) -> Result<usize, HidError> {
+        match self.state.lock().unwrap().reads.pop_front() {
+            Some(Ok(report)) => {
+                let len = report.len().min(buf.len());
+                buf[..len].copy_from_slice(&report[..len]);
+                Ok(len)
+            }
+            Some(Err(message)) => Err(HidError::HidApiError { message }),
+            None => Ok(0),
+        }
+    }
+}
+
+#[test]
+fn test_mock_updates_device_state() {
+    use crate::{Device, DeviceEvent};
+    use std::time::Duration;
+
+    let mock = MockTransport::new();
+    let mut device = Device::with_transport(mock.clone());
+    assert_eq!(mock.writes().len(), 11);
+
+    mock.push_report(&[102, 13, 0, 0, 42, 0, 0, 0]);
+    mock.push_report(&[102, 10, 1, 0, 0, 0, 0, 0]);
+    assert!(matches!(
+        device.wait_for_updates(Duration::from_millis(10)),
+        Ok(DeviceEvent::ChargeLevel(42))
+    ));
+    assert!(matches!(
+        device.wait_for_updates(Duration::from_millis(10)),
+        Ok(DeviceEvent::MicMuted(true))
+    ));
+    assert_eq!(device.battery_level, 42);
+    assert_eq!(device.muted, Some(true));
+    assert!(matches!(
+        device.wait_for_updates(Duration::from_millis(10)),
+        Err(crate::DeviceError::NoResponse())
+    ));
+}
+
+#[test]
+fn test_mock_records_writes() {
+    use crate::Device;
+
+    let mock = MockTransport::new();
+    let device = Device::with_transport(mock.clone());
+    mock.clear_writes();
+
+    device.mute_mic(true).unwrap();
+    device.set_monitor_volume(-2).unwrap();
+    device.get_timeout().unwrap();
+    assert_eq!(
+        mock.writes(),
+        vec![vec![102, 3, 1], vec![102, 5, 0xFE], vec![102, 133]]
+    );
+
+    mock.fail_writes(Some("device disconnected"));
+    assert!(device.mute_mic(false).is_err());
+}
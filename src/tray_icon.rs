@@ -0,0 +1,221 @@
+//! Tray icons drawn in code, so the tray looks the same under any icon theme.
+//!
+//! Shapes are described in unit coordinates (0..1, y pointing down) and
+//! rasterized with 4x4 supersampling for smooth edges.
+
+/// What the icon has to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IconState {
+    pub connected: bool,
+    pub battery_level: u8,
+    pub charging: bool,
+    pub muted: bool,
+}
+
+/// Square ARGB32 image in network byte order, as StatusNotifierItem hosts expect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixmap {
+    pub size: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Color {
+    a: f32,
+    r: f32,
+    g: f32,
+    b: f32,
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color {
+        a: 1.0,
+        r: r as f32 / 255.0,
+        g: g as f32 / 255.0,
+        b: b as f32 / 255.0,
+    }
+}
+
+const HEADSET: Color = rgb(0xE6, 0xE6, 0xE6);
+const OUTLINE: Color = rgb(0x30, 0x30, 0x30);
+const BATTERY_HIGH: Color = rgb(0x4C, 0xAF, 0x50);
+const BATTERY_MEDIUM: Color = rgb(0xFF, 0xC1, 0x07);
+const BATTERY_LOW: Color = rgb(0xF4, 0x43, 0x36);
+const BOLT: Color = rgb(0xFF, 0xEB, 0x3B);
+const SLASH: Color = rgb(0xF4, 0x43, 0x36);
+const DISCONNECTED: Color = Color {
+    a: 0.6,
+    ..rgb(0x80, 0x80, 0x80)
+};
+
+// Battery gauge, below the headset
+const BATTERY_LEFT: f32 = 0.18;
+const BATTERY_RIGHT: f32 = 0.76;
+const BATTERY_TOP: f32 = 0.74;
+const BATTERY_BOTTOM: f32 = 0.96;
+const BATTERY_BORDER: f32 = 0.05;
+
+type Shape = Box<dyn Fn(f32, f32) -> bool>;
+
+fn rect(left: f32, top: f32, right: f32, bottom: f32) -> Shape {
+    Box::new(move |x, y| (left..right).contains(&x) && (top..bottom).contains(&y))
+}
+
+/// Top half of a ring centred on (`cx`, `cy`).
+fn arc(cx: f32, cy: f32, inner: f32, outer: f32) -> Shape {
+    Box::new(move |x, y| {
+        let distance = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+        y <= cy && (inner..outer).contains(&distance)
+    })
+}
+
+/// Segment from `from` to `to`, `width` wide.
+fn line(from: (f32, f32), to: (f32, f32), width: f32) -> Shape {
+    Box::new(move |x, y| {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let t = (((x - from.0) * dx + (y - from.1) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+        let (px, py) = (from.0 + t * dx, from.1 + t * dy);
+        (x - px).powi(2) + (y - py).powi(2) <= (width / 2.0).powi(2)
+    })
+}
+
+/// Filled polygon, even-odd rule.
+fn polygon(points: &'static [(f32, f32)]) -> Shape {
+    Box::new(move |x, y| {
+        let mut inside = false;
+        let mut previous = points[points.len() - 1];
+        for &point in points {
+            if (point.1 > y) != (previous.1 > y)
+                && x < (previous.0 - point.0) * (y - point.1) / (previous.1 - point.1) + point.0
+            {
+                inside = !inside;
+            }
+            previous = point;
+        }
+        inside
+    })
+}
+
+const BOLT_POINTS: [(f32, f32); 6] = [
+    (0.52, 0.70),
+    (0.36, 0.87),
+    (0.46, 0.87),
+    (0.42, 1.00),
+    (0.60, 0.82),
+    (0.50, 0.82),
+];
+
+fn battery_color(level: u8) -> Color {
+    match level {
+        51.. => BATTERY_HIGH,
+        21..=50 => BATTERY_MEDIUM,
+        _ => BATTERY_LOW,
+    }
+}
+
+/// Shapes from bottom to top.
+fn layers(state: &IconState) -> Vec<(Color, Shape)> {
+    let headset = if state.connected { HEADSET } else { DISCONNECTED };
+    let mut layers = vec![
+        (headset, arc(0.5, 0.48, 0.30, 0.38)),
+        (headset, rect(0.06, 0.40, 0.26, 0.70)),
+        (headset, rect(0.74, 0.40, 0.94, 0.70)),
+        (headset, rect(BATTERY_LEFT, BATTERY_TOP, BATTERY_RIGHT, BATTERY_BOTTOM)),
+        (headset, rect(BATTERY_RIGHT, 0.80, 0.82, 0.90)),
+        (
+            OUTLINE,
+            rect(
+                BATTERY_LEFT + BATTERY_BORDER,
+                BATTERY_TOP + BATTERY_BORDER,
+                BATTERY_RIGHT - BATTERY_BORDER,
+                BATTERY_BOTTOM - BATTERY_BORDER,
+            ),
+        ),
+    ];
+    if !state.connected {
+        return layers;
+    }
+
+    let inner_width = BATTERY_RIGHT - BATTERY_LEFT - 2.0 * BATTERY_BORDER;
+    let level = f32::from(state.battery_level.min(100)) / 100.0;
+    layers.push((
+        battery_color(state.battery_level),
+        rect(
+            BATTERY_LEFT + BATTERY_BORDER,
+            BATTERY_TOP + BATTERY_BORDER,
+            BATTERY_LEFT + BATTERY_BORDER + inner_width * level,
+            BATTERY_BOTTOM - BATTERY_BORDER,
+        ),
+    ));
+    if state.charging {
+        layers.push((BOLT, polygon(&BOLT_POINTS)));
+    }
+    if state.muted {
+        layers.push((SLASH, line((0.12, 0.12), (0.88, 0.66), 0.09)));
+    }
+    layers
+}
+
+/// Draws the icon at `size` x `size` pixels.
+pub fn render(state: &IconState, size: usize) -> Pixmap {
+    const SAMPLES: usize = 4;
+    let layers = layers(state);
+    let mut data = Vec::with_capacity(size * size * 4);
+
+    for row in 0..size {
+        for column in 0..size {
+            // Premultiplied sum over the subsamples
+            let (mut a, mut r, mut g, mut b) = (0.0, 0.0, 0.0, 0.0);
+            for sample in 0..SAMPLES * SAMPLES {
+                let x = (column as f32 + ((sample % SAMPLES) as f32 + 0.5) / SAMPLES as f32) / size as f32;
+                let y = (row as f32 + ((sample / SAMPLES) as f32 + 0.5) / SAMPLES as f32) / size as f32;
+                if let Some((color, _)) = layers.iter().rev().find(|(_, shape)| shape(x, y)) {
+                    a += color.a;
+                    r += color.r * color.a;
+                    g += color.g * color.a;
+                    b += color.b * color.a;
+                }
+            }
+
+            let count = (SAMPLES * SAMPLES) as f32;
+            let alpha = a / count;
+            let channel = |value: f32| {
+                if a == 0.0 {
+                    0
+                } else {
+                    (value / a * 255.0).round() as u8
+                }
+            };
+            data.extend_from_slice(&[(alpha * 255.0).round() as u8, channel(r), channel(g), channel(b)]);
+        }
+    }
+
+    Pixmap { size, data }
+}
+
+#[test]
+fn test_render_battery_fill() {
+    let pixel = |pixmap: &Pixmap, x: f32, y: f32| {
+        let index = ((y * pixmap.size as f32) as usize * pixmap.size + (x * pixmap.size as f32) as usize) * 4;
+        pixmap.data[index..index + 4].to_vec()
+    };
+    let mut state = IconState {
+        connected: true,
+        battery_level: 80,
+        charging: false,
+        muted: false,
+    };
+
+    let full = render(&state, 32);
+    assert_eq!(full.data.len(), 32 * 32 * 4);
+    assert_eq!(pixel(&full, 0.28, 0.85), vec![255, 0x4C, 0xAF, 0x50]);
+
+    state.battery_level = 10;
+    let low = render(&state, 32);
+    assert_eq!(pixel(&low, 0.28, 0.85), vec![255, 0xF4, 0x43, 0x36]);
+    assert_eq!(pixel(&low, 0.62, 0.85)[1..], [0x30, 0x30, 0x30]);
+
+    state.connected = false;
+    let disconnected = render(&state, 32);
+    assert!(disconnected.data.chunks(4).all(|argb| argb[1] == argb[2] && argb[2] == argb[3]));
+}